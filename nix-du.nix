@@ -1,4 +1,4 @@
-{ callPackage, pkgs, lib, graphviz, nix, defaultCrateOverrides, xcbuild, pkg-config, boost, darwin, stdenv, rustPlatform }:
+{ callPackage, pkgs, lib, graphviz, nix, sqlite, defaultCrateOverrides, xcbuild, pkg-config, boost, darwin, stdenv, rustPlatform }:
 let
   cargo = import ./Cargo.nix {
     inherit pkgs;
@@ -7,6 +7,7 @@ let
         buildInputs = [
           boost
           nix
+          sqlite
         ] ++ nix.buildInputs;
         nativeBuildInputs = [
           pkg-config
@@ -0,0 +1,553 @@
+// SPDX-License-Identifier: LGPL-3.0
+
+//! Abstraction over the sources of the dependency graph.
+//!
+//! A `StoreBackend` yields store paths, references between them and gc roots, in the
+//! form of a `MemoryStore`. Turning it into a `DepInfos` is common to all backends.
+
+use crate::depgraph::*;
+use crate::gcroots::Root;
+use crate::reduction;
+use petgraph::prelude::NodeIndex;
+use std::collections::HashMap;
+use std::io;
+
+/// What keeps the paths of a store alive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Roots {
+    /// The backend does not know gc roots: paths which no other path references are
+    /// considered as roots.
+    TopLevel,
+    /// Gc roots: the description of the root (usually the path of a symlink) and the
+    /// index of the store path it keeps alive. Paths outside of their closure are dead.
+    GcRoots(Vec<(Path, usize)>),
+    /// Only the closure of these store paths is relevant: the name of the root, and the
+    /// index of the store path. With a single root, the graph is rooted at its store path;
+    /// otherwise each root is a child of a dummy root.
+    Closure(Vec<(Path, usize)>),
+}
+
+/// An in-memory store: the result of reading a backend, and a backend by itself.
+#[derive(Debug, Clone)]
+pub struct MemoryStore {
+    /// store paths, their size in bytes and their metadata
+    paths: Vec<(Path, u64, Option<Box<StorePathInfo>>)>,
+    /// pairs of (referrer, reference), as indices in `paths`
+    references: Vec<(usize, usize)>,
+    index: HashMap<Path, usize>,
+    pub roots: Roots,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        MemoryStore {
+            paths: Vec::new(),
+            references: Vec::new(),
+            index: HashMap::new(),
+            roots: Roots::TopLevel,
+        }
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a store path of size `size`, and returns its index.
+    ///
+    /// If this path is already present, returns its index and leaves its size unchanged.
+    pub fn add_path(&mut self, path: Path, size: u64) -> usize {
+        if let Some(&i) = self.index.get(&path) {
+            return i;
+        }
+        let i = self.paths.len();
+        self.index.insert(path.clone(), i);
+        self.paths.push((path, size, None));
+        i
+    }
+
+    /// Records the metadata of the path with this index.
+    pub fn set_info(&mut self, index: usize, info: StorePathInfo) {
+        self.paths[index].2 = Some(Box::new(info));
+    }
+
+    /// Returns the index of a store path, if present.
+    pub fn index_of(&self, path: &[u8]) -> Option<usize> {
+        self.index.get(path).copied()
+    }
+
+    /// Returns the path with this index.
+    pub fn path(&self, index: usize) -> &Path {
+        &self.paths[index].0
+    }
+
+    /// Records that `from` references `to`, or more generally keeps it alive.
+    ///
+    /// Self references and duplicate references are ignored.
+    pub fn add_reference(&mut self, from: usize, to: usize) {
+        assert!(from < self.paths.len() && to < self.paths.len());
+        if from != to {
+            self.references.push((from, to));
+        }
+    }
+
+    /// Adds gc roots. Roots to absent paths are ignored, as nix ignores roots to invalid
+    /// paths.
+    ///
+    /// Calling this function, even with no roots, makes paths outside the closure of gc
+    /// roots dead.
+    pub fn add_gc_roots(&mut self, roots: impl IntoIterator<Item = Root>) {
+        let mut new: Vec<_> = roots
+            .into_iter()
+            .filter_map(|(link, target)| self.index_of(&target).map(|i| (link, i)))
+            .collect();
+        match &mut self.roots {
+            Roots::GcRoots(r) => r.append(&mut new),
+            _ => self.roots = Roots::GcRoots(new),
+        }
+    }
+
+    /// Builds the corresponding `DepInfos`.
+    pub fn into_dep_infos(self) -> DepInfos {
+        let MemoryStore {
+            paths,
+            mut references,
+            roots,
+            ..
+        } = self;
+        let mut g = DepGraph::with_capacity(paths.len(), references.len());
+        for (path, size, info) in paths {
+            g.add_node(DepNode {
+                description: NodeDescription::Path(path),
+                size,
+                info,
+            });
+        }
+        references.sort_unstable();
+        references.dedup();
+        for (from, to) in references {
+            g.add_edge(NodeIndex::new(from), NodeIndex::new(to), ());
+        }
+        match roots {
+            Roots::TopLevel => {
+                let toplevel: Vec<_> = g.externals(petgraph::Direction::Incoming).collect();
+                let root = g.add_node(DepNode::dummy());
+                for idx in toplevel {
+                    g.add_edge(root, idx, ());
+                }
+                DepInfos::from_graph(g, Some(root))
+            }
+            Roots::GcRoots(roots) => {
+                for (link, target) in roots {
+                    let from = g.add_node(DepNode {
+                        description: NodeDescription::from_raw(link, true),
+                        size: 0,
+                        info: None,
+                    });
+                    g.add_edge(from, NodeIndex::new(target), ());
+                }
+                DepInfos::from_graph(g, None)
+            }
+            Roots::Closure(mut roots) => {
+                let root = if roots.len() == 1 {
+                    NodeIndex::new(roots.pop().unwrap().1)
+                } else {
+                    let root = g.add_node(DepNode::dummy());
+                    for (name, target) in roots {
+                        let link = g.add_node(DepNode {
+                            description: NodeDescription::Link(name),
+                            size: 0,
+                            info: None,
+                        });
+                        g.add_edge(root, link, ());
+                        g.add_edge(link, NodeIndex::new(target), ());
+                    }
+                    root
+                };
+                reduction::keep_reachable(DepInfos::from_graph(g, Some(root)))
+            }
+        }
+    }
+}
+
+/// The error for a malformed input, like a dump of a store or a manifest.
+pub fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// A source of store paths, references and gc roots.
+pub trait StoreBackend {
+    /// Reads the whole content of the backend.
+    fn contents(self) -> io::Result<MemoryStore>;
+}
+
+impl StoreBackend for MemoryStore {
+    fn contents(self) -> io::Result<MemoryStore> {
+        Ok(self)
+    }
+}
+
+/// Reads the dependency graph from `backend`.
+pub fn read<B: StoreBackend>(backend: B) -> io::Result<DepInfos> {
+    Ok(backend.contents()?.into_dep_infos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{add, store_path};
+
+    /// The store of the `simple` integration test, without building it with nix
+    fn simple() -> MemoryStore {
+        let mut store = MemoryStore::new();
+        let (coucou, foo, bar, baz, mux) = (
+            add(&mut store, "coucou", 100_000),
+            add(&mut store, "foo", 100_000),
+            add(&mut store, "bar", 100_000),
+            add(&mut store, "baz", 100_000),
+            add(&mut store, "mux", 100_000),
+        );
+        for &(from, to) in &[
+            (coucou, foo),
+            (bar, foo),
+            (foo, baz),
+            (coucou, mux),
+            (mux, baz),
+        ] {
+            store.add_reference(from, to);
+        }
+        store
+    }
+
+    #[test]
+    fn toplevel() {
+        let di = read(simple()).unwrap();
+        di.check_metadata();
+        assert_eq!(di.metadata.reachable, Reachability::Connected);
+        assert_eq!(
+            di.roots_name(),
+            ["bar", "coucou"].iter().map(|s| s.to_string()).collect()
+        );
+        let di = reduction::condense(di);
+        // coucou+mux, bar, foo+baz
+        assert_eq!(di.graph.node_count(), 4);
+        assert_eq!(di.reachable_size(), 500_000);
+    }
+
+    #[test]
+    fn gc_roots() {
+        let mut store = simple();
+        store.add_reference(0, 1);
+        store.add_gc_roots(vec![
+            (
+                b"/home/user/result".to_vec(),
+                b"/nix/store/aaaa-bar".to_vec(),
+            ),
+            (b"{temp:12}".to_vec(), b"/nix/store/aaaa-gone".to_vec()),
+        ]);
+        let di = read(store).unwrap();
+        di.check_metadata();
+        assert_eq!(di.metadata.reachable, Reachability::Disconnected);
+        assert_eq!(di.graph.edge_count(), 5 + 1 + 1);
+        assert_eq!(di.reachable_size(), 300_000);
+        assert_eq!(di.size(), 500_000);
+    }
+
+    #[test]
+    fn closure() {
+        let mut store = simple();
+        let foo = store.index_of(b"/nix/store/aaaa-foo").unwrap();
+        store.roots = Roots::Closure(vec![(b"/run/foo".to_vec(), foo)]);
+        let di = read(store).unwrap();
+        di.check_metadata();
+        assert_eq!(di.graph.node_count(), 2);
+        assert_eq!(di.graph[di.root].name(), &b"foo"[..]);
+    }
+
+    #[test]
+    fn several_closures() {
+        let mut store = simple();
+        let coucou = store.index_of(b"/nix/store/aaaa-coucou").unwrap();
+        let bar = store.index_of(b"/nix/store/aaaa-bar").unwrap();
+        store.roots = Roots::Closure(vec![
+            (b"/run/current-system".to_vec(), coucou),
+            (b"/home/user/.nix-profile".to_vec(), bar),
+        ]);
+        let di = read(store).unwrap();
+        di.check_metadata();
+        assert_eq!(di.metadata.reachable, Reachability::Connected);
+        assert_eq!(di.graph[di.root].kind(), NodeKind::Dummy);
+        assert_eq!(
+            di.roots_name(),
+            ["/home/user/.nix-profile", "/run/current-system"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect()
+        );
+        // what each root holds exclusively, and what they share
+        let di = reduction::condense(di);
+        assert_eq!(di.graph.node_count(), 4);
+        assert_eq!(di.reachable_size(), 500_000);
+    }
+
+    #[test]
+    fn exclusive_sizes() {
+        let di = read(simple()).unwrap();
+        let exclusive = di.exclusive_sizes();
+        let size_of = |name: &str| {
+            let idx = di
+                .graph
+                .node_indices()
+                .find(|&idx| di.graph[idx].name() == name.as_bytes())
+                .unwrap();
+            exclusive[idx.index()]
+        };
+        assert_eq!(size_of("coucou"), 200_000);
+        assert_eq!(size_of("bar"), 100_000);
+        assert_eq!(size_of("foo"), 100_000);
+        assert_eq!(exclusive[di.root.index()], 500_000);
+        let mut removed = fixedbitset::FixedBitSet::with_capacity(di.graph.node_count());
+        removed.insert(0);
+        // without coucou, mux is dead
+        assert_eq!(di.reachable_without(di.root, &removed).count_ones(..), 4);
+    }
+
+    /// `simple`, with registration times
+    fn registered() -> MemoryStore {
+        let mut store = simple();
+        for &(name, time) in &[("coucou", 5), ("foo", 10), ("baz", 30), ("mux", 20)] {
+            let idx = store.index_of(&store_path(name)).unwrap();
+            store.set_info(
+                idx,
+                StorePathInfo {
+                    registration_time: Some(time),
+                    ..Default::default()
+                },
+            );
+        }
+        store
+    }
+
+    #[test]
+    fn registration_time_of_merged_nodes() {
+        let di = reduction::condense(read(registered()).unwrap());
+        let mut times: Vec<_> = di
+            .graph
+            .raw_nodes()
+            .iter()
+            .map(|n| n.weight.registration_time())
+            .collect();
+        times.sort();
+        // dummy root, bar, coucou+mux, foo+baz
+        assert_eq!(times, vec![None, None, Some(20), Some(30)]);
+    }
+
+    #[test]
+    fn registration_time_of_absorbed_nodes() {
+        let di = reduction::keep(read(registered()).unwrap(), |n| {
+            n.name().as_ref() != b"mux" && n.name().as_ref() != b"baz"
+        });
+        let time = |name: &[u8]| {
+            di.graph
+                .raw_nodes()
+                .iter()
+                .find(|n| n.weight.name().as_ref() == name)
+                .and_then(|n| n.weight.registration_time())
+        };
+        // mux is absorbed by coucou, baz by foo or coucou
+        assert!(time(b"coucou") >= Some(20));
+        assert_eq!(time(b"coucou").max(time(b"foo")), Some(30));
+    }
+
+    /// A store of paths of 100kB named after `nodes`, with `edges` as references. Paths
+    /// without referrers are the roots.
+    fn spec(nodes: &[&str], edges: &[(&str, &str)]) -> MemoryStore {
+        let mut store = MemoryStore::new();
+        for name in nodes {
+            add(&mut store, name, 100_000);
+        }
+        for (from, to) in edges {
+            let index = |name: &str| store.index_of(&store_path(name)).unwrap();
+            let (from, to) = (index(from), index(to));
+            store.add_reference(from, to);
+        }
+        store
+    }
+
+    type Drawing = petgraph::Graph<(String, u64), ()>;
+
+    /// The graph nix-du draws for `store`, with `-s min_size` or `-n nodes`: a node per
+    /// class, with the name it is shown with and the number of paths of 100kB it holds.
+    fn drawn(store: MemoryStore, min_size: u64, nodes: Option<usize>) -> Drawing {
+        let mut di = reduction::condense(read(store).unwrap());
+        let min_size = nodes.map_or(min_size, |n| reduction::min_size_for_nodes(&di, n));
+        if min_size > 0 {
+            di = reduction::keep(di, |n| n.size >= min_size);
+        }
+        let di = reduction::transitive_reduction(di);
+        let mut res = Drawing::new();
+        let mut ids = HashMap::new();
+        for idx in di.graph.node_indices().filter(|&idx| idx != di.root) {
+            let name = di.graph[idx].name();
+            let name = String::from_utf8_lossy(name.rsplit(|&c| c == b'/').next().unwrap())
+                .replace([' ', ':', '.'], "_")
+                .replace(['{', '}'], "");
+            ids.insert(idx, res.add_node((name, di.graph[idx].size / 100_000)));
+        }
+        for edge in di.graph.raw_edges() {
+            if let (Some(&from), Some(&to)) = (ids.get(&edge.source()), ids.get(&edge.target())) {
+                res.add_edge(from, to, ());
+            }
+        }
+        res
+    }
+
+    fn drawing(nodes: &[(&str, u64)], edges: &[(&str, &str)]) -> Drawing {
+        let mut res = Drawing::new();
+        let ids: HashMap<&str, _> = nodes
+            .iter()
+            .map(|&(name, count)| (name, res.add_node((name.to_string(), count))))
+            .collect();
+        for (from, to) in edges {
+            res.add_edge(ids[from], ids[to], ());
+        }
+        res
+    }
+
+    fn assert_drawn_one_of(got: &Drawing, expected: &[Drawing]) {
+        assert!(
+            expected
+                .iter()
+                .any(|e| petgraph::algo::is_isomorphic_matching(
+                    got,
+                    e,
+                    |a, b| a == b,
+                    |_, _| true
+                )),
+            "non-isomorphic graphs.\ngot:\n{:?}\nexpected one of:\n{:?}",
+            petgraph::dot::Dot::new(got),
+            expected
+                .iter()
+                .map(petgraph::dot::Dot::new)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    fn assert_drawn(got: &Drawing, expected: &Drawing) {
+        assert_drawn_one_of(got, std::slice::from_ref(expected))
+    }
+
+    /// `simple`, with an unrelated path `frob` below the size limits
+    fn simple_and_frob() -> MemoryStore {
+        let mut store = simple();
+        store.add_path(b"/nix/store/aaaa-frob".to_vec(), 100_000);
+        store
+    }
+
+    /// a, b, c, d, e, f; a -> d, b -> d, c -> e, d -> e, e -> f
+    fn chain() -> MemoryStore {
+        spec(
+            &["a", "b", "c", "d", "e", "f"],
+            &[("a", "d"), ("b", "d"), ("c", "e"), ("d", "e"), ("e", "f")],
+        )
+    }
+
+    /// when d is filtered out, it is merged into a or b
+    fn chain_filtered() -> Vec<Drawing> {
+        let edges = [("a", "e"), ("b", "e"), ("c", "e")];
+        vec![
+            drawing(&[("a", 2), ("b", 1), ("c", 1), ("e", 2)], &edges),
+            drawing(
+                &[("a", 2), ("b", 1), ("c", 1), ("e", 2)],
+                &[edges[0], edges[1], edges[2], ("b", "a")],
+            ),
+            drawing(&[("a", 1), ("b", 2), ("c", 1), ("e", 2)], &edges),
+            drawing(
+                &[("a", 1), ("b", 2), ("c", 1), ("e", 2)],
+                &[edges[0], edges[1], edges[2], ("a", "b")],
+            ),
+        ]
+    }
+
+    #[test]
+    fn drawn_k2_1() {
+        let store = spec(
+            &["coucou", "foo", "bar"],
+            &[("coucou", "foo"), ("bar", "foo")],
+        );
+        let expected = drawing(
+            &[("coucou", 1), ("bar", 1), ("foo", 1)],
+            &[("coucou", "foo"), ("bar", "foo")],
+        );
+        assert_drawn(&drawn(store, 0, None), &expected);
+    }
+
+    #[test]
+    fn drawn_simple() {
+        let expected = drawing(
+            &[("coucou", 2), ("bar", 1), ("foo", 2)],
+            &[("coucou", "foo"), ("bar", "foo")],
+        );
+        assert_drawn(&drawn(simple(), 0, None), &expected);
+        // the root and what it holds are larger than the limit
+        assert_drawn(&drawn(simple(), 150_000, None), &expected);
+        assert_drawn(&drawn(simple(), 0, Some(2)), &expected);
+    }
+
+    #[test]
+    fn drawn_filtered_root() {
+        // frob is gathered with what else is filtered out
+        let expected = drawing(
+            &[("coucou", 2), ("bar", 1), ("foo", 2), ("filtered_out", 1)],
+            &[("coucou", "foo"), ("bar", "foo")],
+        );
+        assert_drawn(&drawn(simple_and_frob(), 150_000, None), &expected);
+        assert_drawn(&drawn(simple_and_frob(), 0, Some(2)), &expected);
+    }
+
+    #[test]
+    fn drawn_filtered_non_root() {
+        assert_drawn_one_of(&drawn(chain(), 150_000, None), &chain_filtered());
+        assert_drawn_one_of(&drawn(chain(), 0, Some(1)), &chain_filtered());
+    }
+
+    #[test]
+    fn drawn_closure() {
+        let mut store = spec(
+            &["a", "b", "c", "d", "e", "f", "g", "h", "i", "j"],
+            &[
+                ("a", "b"),
+                ("c", "d"),
+                ("d", "e"),
+                ("e", "j"),
+                ("e", "g"),
+                ("d", "f"),
+                ("f", "g"),
+                ("c", "h"),
+                ("h", "i"),
+            ],
+        );
+        let d = store.index_of(b"/nix/store/aaaa-d").unwrap();
+        store.roots = Roots::Closure(vec![(b"/nix/store/aaaa-d".to_vec(), d)]);
+        let expected = drawing(&[("e", 2), ("g", 1), ("f", 1)], &[("e", "g"), ("f", "g")]);
+        assert_drawn(&drawn(store, 0, None), &expected);
+    }
+
+    #[test]
+    fn drawn_several_closures() {
+        let mut store = simple();
+        let coucou = store.index_of(b"/nix/store/aaaa-coucou").unwrap();
+        let bar = store.index_of(b"/nix/store/aaaa-bar").unwrap();
+        store.roots = Roots::Closure(vec![
+            (b"/roots/coucou".to_vec(), coucou),
+            (b"/roots/bar".to_vec(), bar),
+        ]);
+        // what each root holds exclusively, and what they share
+        let expected = drawing(
+            &[("coucou", 2), ("bar", 1), ("foo", 2)],
+            &[("coucou", "foo"), ("bar", "foo")],
+        );
+        assert_drawn(&drawn(store, 0, None), &expected);
+    }
+}
@@ -0,0 +1,288 @@
+// SPDX-License-Identifier: LGPL-3.0
+
+//! Reads the dependency graph from the `.narinfo` files of a binary cache in a local
+//! directory, as created by `nix copy --to file:///some/dir`.
+
+use crate::backend::{invalid_data, MemoryStore, StoreBackend};
+use crate::depgraph::*;
+use crate::gcroots::{parse_print_roots, store_dir_of};
+use crate::msg::*;
+use std::io::{self, BufRead};
+
+/// Which size of a path in a binary cache is counted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheSize {
+    /// The size of the path once unpacked in a store, `NarSize`
+    Nar,
+    /// The size of the compressed nar in the cache, `FileSize`
+    File,
+}
+
+/// The fields of a `.narinfo` file nix-du cares about
+#[derive(Debug, PartialEq, Eq)]
+struct NarInfo {
+    path: Path,
+    nar_size: u64,
+    /// absent for uncompressed nars
+    file_size: Option<u64>,
+    /// base names of the references
+    references: Vec<Path>,
+    deriver: Option<Path>,
+    content_addressed: bool,
+    signed: bool,
+}
+
+/// Parses the content of a `.narinfo` file: `Key: value` lines.
+fn parse_narinfo(content: &[u8]) -> io::Result<NarInfo> {
+    let mut path = None;
+    let mut nar_size = None;
+    let mut file_size = None;
+    let mut references = Vec::new();
+    let mut deriver = None;
+    let mut content_addressed = false;
+    let mut signed = false;
+    let parse_size = |value: &[u8]| {
+        std::str::from_utf8(value)
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .ok_or_else(|| invalid_data(format!("invalid size {}", String::from_utf8_lossy(value))))
+    };
+    for line in content.split(|&c| c == b'\n') {
+        let sep = b": ";
+        let i = match line.windows(sep.len()).position(|w| w == sep) {
+            Some(i) => i,
+            None => continue,
+        };
+        let (key, value) = (&line[..i], &line[i + sep.len()..]);
+        match key {
+            b"StorePath" => path = Some(value.to_vec()),
+            b"NarSize" => nar_size = Some(parse_size(value)?),
+            b"FileSize" => file_size = Some(parse_size(value)?),
+            b"References" => {
+                references = value
+                    .split(|&c| c == b' ')
+                    .filter(|r| !r.is_empty())
+                    .map(<[u8]>::to_vec)
+                    .collect()
+            }
+            b"Deriver" if value != b"unknown-deriver" => deriver = Some(value.to_vec()),
+            b"CA" => content_addressed = !value.is_empty(),
+            b"Sig" => signed = true,
+            _ => (),
+        }
+    }
+    match (path, nar_size) {
+        (Some(path), Some(nar_size)) => Ok(NarInfo {
+            path,
+            nar_size,
+            file_size,
+            references,
+            deriver,
+            content_addressed,
+            signed,
+        }),
+        _ => Err(invalid_data("narinfo without StorePath or NarSize".into())),
+    }
+}
+
+/// The binary cache in directory `dir`, counting `size` for each path.
+///
+/// If `roots` is given, it is parsed as the output of `nix-store --gc --print-roots`
+/// (possibly with only store paths, one per line), and paths not in the closure of
+/// these roots appear as dead, that is as candidates for pruning. Otherwise, the paths
+/// which are not referenced by any other path are the roots.
+///
+/// References to paths absent from the cache are ignored with a warning. A binary cache
+/// has no `keep-derivations` setting, so outputs do not keep their derivation alive.
+pub struct BinaryCache<S> {
+    pub dir: std::path::PathBuf,
+    pub roots: Option<S>,
+    pub size: CacheSize,
+}
+
+impl<S: BufRead> StoreBackend for BinaryCache<S> {
+    fn contents(self) -> io::Result<MemoryStore> {
+        let mut infos = Vec::new();
+        for entry in self.dir.read_dir()? {
+            let entry = entry?;
+            if entry.path().extension() != Some("narinfo".as_ref()) {
+                continue;
+            }
+            let content = std::fs::read(entry.path())?;
+            let info = parse_narinfo(&content)
+                .map_err(|e| invalid_data(format!("{}: {}", entry.path().display(), e)))?;
+            infos.push(info);
+        }
+
+        let mut store = MemoryStore::new();
+        for info in &infos {
+            let size = match self.size {
+                CacheSize::Nar => info.nar_size,
+                CacheSize::File => info.file_size.unwrap_or(info.nar_size),
+            };
+            store.add_path(info.path.clone(), size);
+        }
+        let mut missing = 0;
+        for info in &infos {
+            let from = store.index_of(&info.path).unwrap();
+            let store_dir = store_dir_of(&info.path).unwrap_or(b"/nix/store");
+            let full_path = |name: &[u8]| {
+                let mut res = store_dir.to_vec();
+                res.push(b'/');
+                res.extend_from_slice(name);
+                res
+            };
+            for reference in &info.references {
+                match store.index_of(&full_path(reference)) {
+                    Some(to) => store.add_reference(from, to),
+                    None => missing += 1,
+                }
+            }
+            store.set_info(
+                from,
+                StorePathInfo {
+                    deriver: info
+                        .deriver
+                        .as_ref()
+                        .map(|d| String::from_utf8_lossy(&full_path(d)).into_owned()),
+                    registration_time: None,
+                    content_addressed: info.content_addressed,
+                    signed: info.signed,
+                    // paths in a binary cache are meant to be substituted
+                    ultimate: false,
+                },
+            );
+        }
+        if missing > 0 {
+            msg!(
+                "warning: {} references to paths absent from the cache were ignored\n",
+                missing
+            );
+        }
+
+        if let Some(roots) = self.roots {
+            store.add_gc_roots(parse_print_roots(roots)?);
+        }
+        Ok(store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend;
+    use crate::testutil::TempDir;
+
+    fn read_binary_cache(
+        dir: &std::path::Path,
+        roots: Option<&[u8]>,
+        size: CacheSize,
+    ) -> io::Result<DepInfos> {
+        backend::read(BinaryCache {
+            dir: dir.to_owned(),
+            roots,
+            size,
+        })
+    }
+
+    const FOO: &str = "StorePath: /nix/store/aaaa-foo
+URL: nar/1111.nar.xz
+Compression: xz
+FileHash: sha256:1111
+FileSize: 40
+NarHash: sha256:2222
+NarSize: 100
+References: aaaa-foo bbbb-bar zzzz-gone
+Deriver: cccc-foo.drv
+Sig: cache.example.org-1:xxxx
+";
+
+    const BAR: &str = "StorePath: /nix/store/bbbb-bar
+URL: nar/3333.nar
+Compression: none
+NarHash: sha256:3333
+NarSize: 50
+References:
+Deriver: unknown-deriver
+";
+
+    const FOO_DRV: &str = "StorePath: /nix/store/cccc-foo.drv
+URL: nar/4444.nar
+Compression: none
+NarHash: sha256:4444
+NarSize: 10
+References:
+";
+
+    #[test]
+    fn narinfo() {
+        let info = parse_narinfo(FOO.as_bytes()).unwrap();
+        assert_eq!(
+            info,
+            NarInfo {
+                path: b"/nix/store/aaaa-foo".to_vec(),
+                nar_size: 100,
+                file_size: Some(40),
+                references: vec![
+                    b"aaaa-foo".to_vec(),
+                    b"bbbb-bar".to_vec(),
+                    b"zzzz-gone".to_vec()
+                ],
+                deriver: Some(b"cccc-foo.drv".to_vec()),
+                content_addressed: false,
+                signed: true,
+            }
+        );
+        let info = parse_narinfo(BAR.as_bytes()).unwrap();
+        assert_eq!(info.references, Vec::<Path>::new());
+        assert_eq!(info.deriver, None);
+        assert!(parse_narinfo(b"StorePath: /nix/store/aaaa-foo\n").is_err());
+    }
+
+    #[test]
+    fn read_cache() {
+        let tmp = TempDir::new("cache");
+        std::fs::create_dir_all(tmp.join("nar")).unwrap();
+        std::fs::write(tmp.join("aaaa.narinfo"), FOO).unwrap();
+        std::fs::write(tmp.join("bbbb.narinfo"), BAR).unwrap();
+        std::fs::write(tmp.join("cccc.narinfo"), FOO_DRV).unwrap();
+        std::fs::write(tmp.join("nix-cache-info"), "StoreDir: /nix/store\n").unwrap();
+        let nar = read_binary_cache(&tmp, None, CacheSize::Nar);
+        let file = read_binary_cache(&tmp, None, CacheSize::File);
+        let roots: &[u8] = b"/nix/store/bbbb-bar\n";
+        let rooted = read_binary_cache(&tmp, Some(roots), CacheSize::Nar);
+        let foo: &[u8] = b"/nix/store/aaaa-foo\n";
+        let rooted_foo = read_binary_cache(&tmp, Some(foo), CacheSize::Nar);
+
+        let nar = nar.unwrap();
+        nar.check_metadata();
+        assert_eq!(nar.graph.node_count(), 4);
+        assert_eq!(nar.reachable_size(), 160);
+        assert_eq!(
+            nar.roots_name(),
+            ["foo".to_string(), "foo.drv".to_string()]
+                .iter()
+                .cloned()
+                .collect()
+        );
+
+        let file = file.unwrap();
+        file.check_metadata();
+        assert_eq!(file.reachable_size(), 100);
+
+        let rooted = rooted.unwrap();
+        rooted.check_metadata();
+        assert_eq!(rooted.metadata.reachable, Reachability::Disconnected);
+        assert_eq!(
+            rooted.metadata.size[DedupAwareness::Unaware][Reachability::Connected],
+            Some(50)
+        );
+
+        // the derivation of foo is not kept alive by foo
+        let rooted_foo = rooted_foo.unwrap();
+        assert_eq!(
+            rooted_foo.metadata.size[DedupAwareness::Unaware][Reachability::Connected],
+            Some(150)
+        );
+    }
+}
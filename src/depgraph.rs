@@ -1,29 +1,38 @@
 // SPDX-License-Identifier: LGPL-3.0
 
+use crate::backend::{MemoryStore, Roots, StoreBackend};
 use crate::bindings;
+use crate::gcroots::Root;
+use crate::msg::*;
+use crate::rules;
+use crate::sysroot;
 use enum_map::{enum_map, Enum};
 use std;
 use std::borrow::Cow;
-#[cfg(test)]
 use std::collections;
-use std::ffi::{CStr, OsStr, OsString};
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString, OsStr};
 use std::fmt::{self, Display};
-use std::os::raw::c_void;
+use std::os::raw::{c_char, c_void};
 use std::os::unix::ffi::OsStrExt;
-use std::os::unix::ffi::OsStringExt;
 use std::path::PathBuf;
+use std::sync::Mutex;
 use std::time::{Duration, SystemTime};
 use std::vec::Vec;
 
+use fixedbitset::FixedBitSet;
 use petgraph::prelude::NodeIndex;
-use petgraph::visit::Dfs;
 use petgraph::visit::IntoNodeReferences;
+use petgraph::visit::{Dfs, NodeFiltered};
 
 use enum_map::EnumMap;
 
 use lazy_static::lazy_static;
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum NodeKind {
     Path,
     Link,
@@ -33,13 +42,17 @@ pub enum NodeKind {
     Temporary,
     Transient,
     Shared,
+    Process,
+    Build,
+    Group,
+    Other,
 }
 
 impl NodeKind {
     pub fn is_gc_root(self) -> bool {
         use self::NodeKind::*;
         match self {
-            Transient | Link | Memory | Temporary => true,
+            Transient | Link | Memory | Temporary | Process | Build | Group | Other => true,
             FilteredOut | Path | Shared | Dummy => false,
         }
     }
@@ -48,7 +61,8 @@ impl NodeKind {
         use self::NodeKind::*;
         match self {
             Memory | Temporary => true,
-            Transient | Link | FilteredOut | Path | Shared | Dummy => false,
+            Transient | Link | FilteredOut | Path | Shared | Dummy | Process | Build | Group
+            | Other => false,
         }
     }
 }
@@ -73,13 +87,22 @@ pub enum NodeDescription {
     Temporary(Path),
     /// Symbolises a set of inodes de-duplicated by store optimisation
     Shared(Path),
+    /// A node gathering the in-memory roots of a running process, with its label
+    Process(Path),
+    /// A node gathering the temporary roots of a nix process, with its label
+    Build(Path),
+    /// A node gathering gc roots chosen with `--group-roots`, with its label
+    Group(Path),
+    /// A gc root of a kind unknown to nix-du, as reported by nix, for example a new kind
+    /// of in-memory root
+    Other(Path),
 }
 
 const SHARED_PREFIX: &[u8] = b"shared:";
 
 /// Converts `/home/symphorien/.cache/lorri/gc_roots/02ebed43adca1d7ca863ce9b0a537205/gc_root/shell_gc_root/` into `/home/symphorien/src/lorri/tests/integration/bug23_gopath/shell.nix`
 fn resolve_lorri_root(path: &[u8]) -> std::io::Result<PathBuf> {
-    let path = std::path::Path::new(std::ffi::OsStr::from_bytes(path));
+    let path = sysroot::host_path(path);
     let mut path = match path.parent() {
         Some(p) => p.to_owned(),
         None => {
@@ -93,15 +116,110 @@ fn resolve_lorri_root(path: &[u8]) -> std::io::Result<PathBuf> {
     std::fs::read_link(path)
 }
 
-/// A struct for human readable age of a link
+/// Reads the NixOS version, the kernel version and the configuration revision of the
+/// NixOS generation whose store path is `generation`, as far as they can be found.
+fn nixos_generation_details(generation: &std::path::Path) -> Vec<String> {
+    lazy_static! {
+        static ref REVISION: regex::bytes::Regex =
+            regex::bytes::Regex::new(r#""configurationRevision": ?"([^"]*)""#)
+                .expect("regex compilation failed");
+    }
+    let read = |relative: &str| {
+        let path = sysroot::canonicalize(&generation.join(relative)).ok()?;
+        std::fs::read(sysroot::host_path(path.as_os_str().as_bytes())).ok()
+    };
+    let mut details = Vec::new();
+    if let Some(version) = read("nixos-version") {
+        details.push(String::from_utf8_lossy(&version).trim().to_string());
+    }
+    // $out/kernel points to $linux/bzImage or similar
+    if let Ok(kernel) = sysroot::canonicalize(&generation.join("kernel")) {
+        let package = kernel.parent().and_then(|p| p.file_name());
+        let name = package.map(|p| p.as_bytes()).and_then(|p| {
+            let i = p.iter().position(|&c| c == b'-')?;
+            Some(&p[i + 1..])
+        });
+        if let Some(name) = name {
+            let (_, version) = split_name(name);
+            details.push(format!("linux {}", String::from_utf8_lossy(version)));
+        }
+    }
+    // the json output of `nixos-version --json` is embedded in the script
+    if let Some(script) = read("sw/bin/nixos-version") {
+        if let Some(c) = REVISION.captures(&script) {
+            let revision = String::from_utf8_lossy(c.get(1).unwrap().as_bytes());
+            details.push(format!("rev {revision}"));
+        }
+    }
+    details
+}
+
+/// Describes the NixOS generation pointed to by `link`, like
+/// `NixOS generation 42 (24.05.20240601.abcdef, linux 6.6.32), current, booted`. Only the
+/// number is known when the files of the analysed system are not available.
+fn describe_nixos_generation(link: &str, gen: &str) -> String {
+    let mut res = format!("NixOS generation {gen}");
+    if !sysroot::is_available() {
+        return res;
+    }
+    let generation = match sysroot::canonicalize(std::path::Path::new(link)) {
+        Ok(g) => g,
+        Err(_) => return res,
+    };
+    let details = nixos_generation_details(&generation);
+    if !details.is_empty() {
+        res.push_str(&format!(" ({})", details.join(", ")));
+    }
+    for (link, mark) in &[
+        ("/run/current-system", "current"),
+        ("/run/booted-system", "booted"),
+    ] {
+        if sysroot::canonicalize(std::path::Path::new(link))
+            .ok()
+            .as_ref()
+            == Some(&generation)
+        {
+            res.push_str(&format!(", {mark}"));
+        }
+    }
+    res
+}
+
+/// returns, for each node of `graph`, the sum of the sizes of the nodes it dominates,
+/// that is the nodes which would no longer be reachable from `root` without it,
+/// including itself.
+fn dominated_sizes<N>(
+    graph: &petgraph::Graph<N, ()>,
+    root: NodeIndex,
+    size: impl Fn(NodeIndex) -> u64,
+) -> Vec<u64> {
+    let dominators = petgraph::algo::dominators::simple_fast(graph, root);
+    // a node is visited by a dfs after its dominators
+    let mut order = Vec::with_capacity(graph.node_count());
+    let mut dfs = Dfs::new(graph, root);
+    while let Some(idx) = dfs.next(graph) {
+        order.push(idx);
+    }
+    let mut sizes: Vec<u64> = graph.node_indices().map(size).collect();
+    for &idx in order.iter().rev() {
+        if let Some(dominator) = dominators.immediate_dominator(idx) {
+            sizes[dominator.index()] += sizes[idx.index()];
+        }
+    }
+    sizes
+}
+
+const DAY: Duration = Duration::from_secs(3600 * 24);
+const MONTH: Duration = Duration::from_secs(3600 * 24 * 30);
+const YEAR: Duration = Duration::from_secs(3600 * 24 * 365);
+
+/// A struct for human readable age of a link or a store path
 ///
 /// displays as `, 3d ago` where units are d=day, m=month and y=year.
-struct LinkAge(Option<SystemTime>);
-impl Display for LinkAge {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Age(pub Option<SystemTime>);
+impl Display for Age {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        const DAY: Duration = Duration::from_secs(3600 * 24);
-        const MONTH: Duration = Duration::from_secs(3600 * 24 * 30);
-        const YEAR: Duration = Duration::from_secs(3600 * 24 * 365);
         if let Some(t) = &self.0 {
             if let Ok(duration) = t.elapsed() {
                 return if duration < DAY {
@@ -119,10 +237,257 @@ impl Display for LinkAge {
     }
 }
 
+/// Parses a duration like `90d`, with the units of `Age` plus h=hour and w=week.
+pub fn parse_age(s: &str) -> Result<Duration, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (n, unit) = s.split_at(split);
+    let n: u64 = n
+        .parse()
+        .map_err(|_| format!("expected a number followed by a unit, like 90d, got {s:?}"))?;
+    let unit = match unit {
+        "h" => Duration::from_secs(3600),
+        "d" => DAY,
+        "w" => DAY * 7,
+        "m" => MONTH,
+        "y" => YEAR,
+        _ => {
+            return Err(format!(
+                "unknown unit {unit:?}, expected one of h, d, w, m, y"
+            ))
+        }
+    };
+    n.checked_mul(unit.as_secs())
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("{s} is too long"))
+}
+
+/// Why a gc root is probably forgotten, see `NodeDescription::orphan`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Orphan {
+    /// the link, or the directory it lived in, does not exist any more
+    Gone,
+    /// the link is in a git checkout where nothing happened since this time
+    StaleCheckout(PathBuf, SystemTime),
+    /// the link is in a temporary directory
+    Temporary,
+}
+
+impl Display for Orphan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Orphan::Gone => write!(f, "deleted or moved"),
+            Orphan::StaleCheckout(dir, time) => write!(
+                f,
+                "checkout {} last used {}",
+                dir.display(),
+                Age(Some(*time)).to_string().trim_start_matches(", ")
+            ),
+            Orphan::Temporary => write!(f, "in a temporary directory"),
+        }
+    }
+}
+
+const TEMP_DIRS: &[&str] = &["/tmp/", "/var/tmp/"];
+
+/// The last time something happened in the git checkout whose `.git` is `git`: commits,
+/// checkouts, fetches or staging files. `.git` is a file in worktrees.
+fn checkout_activity(git: &std::path::Path) -> Option<SystemTime> {
+    ["", "index", "HEAD", "logs/HEAD", "FETCH_HEAD"]
+        .iter()
+        .filter_map(|file| git.join(file).metadata().and_then(|m| m.modified()).ok())
+        .max()
+}
+
+/// Tells whether the gc root at `link` looks forgotten: see `Orphan`. Checkouts are
+/// stale when nothing happened there for `stale_after`.
+fn orphan_status(link: &[u8], stale_after: Duration) -> Option<Orphan> {
+    let host = sysroot::host_path(link);
+    match host.symlink_metadata() {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Some(Orphan::Gone),
+        _ => (),
+    }
+    let system = std::path::Path::new(OsStr::from_bytes(link));
+    for dir in system.ancestors().skip(1) {
+        let git = sysroot::host_path(dir.as_os_str().as_bytes()).join(".git");
+        if git.symlink_metadata().is_err() {
+            continue;
+        }
+        match checkout_activity(&git) {
+            Some(time) if time.elapsed().is_ok_and(|e| e > stale_after) => {
+                return Some(Orphan::StaleCheckout(dir.to_owned(), time))
+            }
+            _ => break,
+        }
+    }
+    if TEMP_DIRS.iter().any(|dir| link.starts_with(dir.as_bytes())) {
+        return Some(Orphan::Temporary);
+    }
+    None
+}
+
+/// Splits the path of a generation of a profile, like
+/// `/nix/var/nix/profiles/system-42-link`, into the path of the profile and the number of
+/// the generation.
+pub fn profile_generation(link: &[u8]) -> Option<(&[u8], u64)> {
+    lazy_static! {
+        static ref GENERATION: regex::bytes::Regex =
+            regex::bytes::Regex::new(r"^(.*)-([0-9]+)-link$").expect("regex compilation failed");
+    };
+    let c = GENERATION.captures(link)?;
+    let number = std::str::from_utf8(c.get(2).unwrap().as_bytes()).ok()?;
+    Some((c.get(1).unwrap().as_bytes(), number.parse().ok()?))
+}
+
+/// Whether deleting the gc root at `link` would break the running system or a profile
+/// in use: `/run/current-system`, `/run/booted-system`, the current home-manager
+/// generation, and the generation a profile currently points to. The latter can only be
+/// known when the files of the analysed system are available.
+fn is_current_link(link: &[u8]) -> bool {
+    if link == b"/run/current-system" || link == b"/run/booted-system" {
+        return true;
+    }
+    if link.ends_with(b"/current-home") {
+        return true;
+    }
+    let profile = match profile_generation(link) {
+        Some((profile, _)) if sysroot::is_available() => profile,
+        _ => return false,
+    };
+    let canonical =
+        |path: &[u8]| sysroot::canonicalize(std::path::Path::new(OsStr::from_bytes(path))).ok();
+    match canonical(profile) {
+        Some(current) => Some(current) == canonical(link),
+        None => false,
+    }
+}
+
+/// Describes a generation of a profile: `profile` is the name of the profile, for example
+/// `profile` for the default one, or `home-manager`.
+fn describe_profile(user: &str, profile: &str, gen: &str) -> (String, &'static str) {
+    match profile {
+        "profile" => (
+            format!("generation {gen} of {user}'s profile"),
+            "user profile",
+        ),
+        "home-manager" => (
+            format!("home-manager generation {gen} of {user}"),
+            "home-manager",
+        ),
+        _ => (
+            format!("generation {gen} of {user}'s profile {profile}"),
+            "user profile",
+        ),
+    }
+}
+
+/// Like `describe_link`, but only describes each link once: this may read files of the
+/// analysed system, and is needed each time the name or the category of a link is.
+fn classify_link(path: &str) -> Option<(String, &'static str)> {
+    lazy_static! {
+        static ref CACHE: Mutex<collections::HashMap<String, Option<(String, &'static str)>>> =
+            Mutex::new(collections::HashMap::new());
+    };
+    if let Some(res) = CACHE.lock().unwrap().get(path) {
+        return res.clone();
+    }
+    let res = describe_link(path);
+    CACHE.lock().unwrap().insert(path.to_owned(), res.clone());
+    res
+}
+
+/// Describes well known kinds of indirect roots, and those matched by user supplied
+/// rules: returns a label and a category, or `None`.
+fn describe_link(path: &str) -> Option<(String, &'static str)> {
+    lazy_static! {
+        static ref PER_USER_PROFILE: regex::Regex =
+            regex::Regex::new(r"^/(?:.*)/profiles/per-user/([^/]*)/([^/]*)-([0-9]*)-link$")
+                .expect("regex compilation failed");
+        /// profiles in `$XDG_STATE_HOME`, with `use-xdg-base-directories` or nix >= 2.14
+        static ref HOME_PROFILE: regex::Regex = regex::Regex::new(
+            r"^/(?:home/|Users/)?([^/]*)/\.local/state/nix/profiles/([^/]*)-([0-9]*)-link$"
+        )
+        .expect("regex compilation failed");
+        static ref SYSTEM_PROFILE: regex::Regex =
+            regex::Regex::new(r"^/(?:.*)/profiles/system-([0-9]*)-link$")
+                .expect("regex compilation failed");
+        static ref HOME_MANAGER: regex::Regex = regex::Regex::new(
+            r"^/(?:home/|Users/)?([^/]*)/\.local/state/home-manager/gcroots/current-home$|^/(?:.*)/gcroots/per-user/([^/]*)/current-home$"
+        )
+        .expect("regex compilation failed");
+        static ref LORRI: regex::Regex = regex::Regex::new(
+            r"^/home/([^/]*)/.cache/lorri/gc_roots/(?:[^/]*)/gc_root/shell_gc_root$"
+        )
+        .expect("regex compilation failed");
+        static ref DIRENV: regex::Regex =
+            regex::Regex::new(r"^(/.*)/\.direnv/(flake-profile|nix-profile|flake-inputs/)")
+                .expect("regex compilation failed");
+        static ref DEVENV: regex::Regex =
+            regex::Regex::new(r"^(/.*)/\.devenv/(?:gc/|profile$)")
+                .expect("regex compilation failed");
+    };
+    if let Some(res) = rules::rules().iter().find_map(|rule| rule.apply(path)) {
+        return Some(res);
+    }
+    if let Some(c) = PER_USER_PROFILE
+        .captures(path)
+        .or_else(|| HOME_PROFILE.captures(path))
+    {
+        let user = c.get(1).unwrap().as_str();
+        let profile = c.get(2).unwrap().as_str();
+        let gen = c.get(3).unwrap().as_str();
+        return Some(describe_profile(user, profile, gen));
+    }
+    if let Some(c) = SYSTEM_PROFILE.captures(path) {
+        let gen = c.get(1).unwrap().as_str();
+        return Some((describe_nixos_generation(path, gen), "system"));
+    }
+    if let Some(c) = HOME_MANAGER.captures(path) {
+        let user = c.get(1).or_else(|| c.get(2)).unwrap().as_str();
+        return Some((
+            format!("{user}'s current home-manager generation"),
+            "home-manager",
+        ));
+    }
+    if let Some(c) = LORRI.captures(path) {
+        let user = c.get(1).unwrap().as_str();
+        let nix_file = resolve_lorri_root(path.as_bytes()).ok()?;
+        return Some((
+            format!("{user}'s lorri cache for {}", nix_file.display()),
+            "lorri",
+        ));
+    }
+    if let Some(c) = DIRENV.captures(path) {
+        let project = c.get(1).unwrap().as_str();
+        let label = match c.get(2).unwrap().as_str() {
+            "flake-inputs/" => format!("nix-direnv flake inputs of {project}"),
+            _ => format!("nix-direnv environment of {project}"),
+        };
+        return Some((label, "nix-direnv"));
+    }
+    if let Some(c) = DEVENV.captures(path) {
+        let project = c.get(1).unwrap().as_str();
+        return Some((format!("devenv environment of {project}"), "devenv"));
+    }
+    None
+}
+
+/// Splits the name of a store path (without hash) into package name and version, like
+/// nix's `DrvName`: the version starts after the first dash not followed by a letter.
+pub fn split_name(name: &[u8]) -> (&[u8], &[u8]) {
+    let dash = name
+        .windows(2)
+        .position(|w| w[0] == b'-' && !w[1].is_ascii_alphabetic());
+    match dash {
+        Some(i) => (&name[..i], &name[i + 1..]),
+        None => (name, b""),
+    }
+}
+
 impl NodeDescription {
     /// a short but human readable description of the node
     /// for a store path, only shows the name
-    /// for a gc root, tells if it's a per-user profile, a NixOS generation, or a lorri gc
+    /// for a gc root, tells if it's a profile generation, a NixOS generation, or the
+    /// environment of a project (lorri, nix-direnv, devenv)
     /// does some amount of work, so you might want to cache it.
     pub fn name(&self) -> Cow<'_, [u8]> {
         use self::NodeDescription::*;
@@ -130,16 +495,6 @@ impl NodeDescription {
             static ref STORE_PATH: regex::bytes::Regex =
                 regex::bytes::Regex::new(r"^/(?:.*)/[a-z0-9]*-([^/]*)$")
                     .expect("regex compilation failed");
-            static ref PER_USER_PROFILE: regex::Regex =
-                regex::Regex::new(r"^/(?:.*)/profiles/per-user/([^/]*)/([^/]*)-([0-9]*)-link$")
-                    .expect("regex compilation failed");
-            static ref SYSTEM_PROFILE: regex::Regex =
-                regex::Regex::new(r"^/(?:.*)/profiles/system-([0-9]*)-link$")
-                    .expect("regex compilation failed");
-            static ref LORRI: regex::Regex = regex::Regex::new(
-                r"^/home/([^/]*)/.cache/lorri/gc_roots/(?:[^/]*)/gc_root/shell_gc_root$"
-            )
-            .expect("regex compilation failed");
         };
         match self {
             Path(path) => match STORE_PATH.captures(&path) {
@@ -149,62 +504,16 @@ impl NodeDescription {
                 }
                 None => Cow::Borrowed(&path),
             },
-            Link(path) => match std::str::from_utf8(path) {
-                Ok(path_str) => {
-                    let link_age = match std::path::Path::new(std::ffi::OsStr::from_bytes(path))
-                        .symlink_metadata()
-                        .map(|m| m.modified())
-                    {
-                        Ok(Ok(time)) => LinkAge(Some(time)),
-                        _ => LinkAge(None),
-                    };
-                    let fancy_desc = match PER_USER_PROFILE.captures(&path_str) {
-                        Some(c) => {
-                            let user = c.get(1).unwrap().as_str();
-                            let profile = c.get(2).unwrap().as_str();
-                            let gen = c.get(3).unwrap().as_str();
-                            let desc = if profile == "profile" {
-                                format!("generation {gen} of {user}'s profile{link_age}")
-                            } else {
-                                format!("generation {gen} of {user}'s profile {profile}{link_age}")
-                            };
-                            Some(desc)
-                        }
-                        None => match SYSTEM_PROFILE.captures(&path_str) {
-                            Some(c) => {
-                                let gen = c.get(1).unwrap().as_str();
-                                let desc = format!("NixOS generation {gen}{link_age}");
-                                Some(desc)
-                            }
-                            None => match LORRI.captures(&path_str) {
-                                Some(c) => {
-                                    let user = c.get(1).unwrap().as_str();
-                                    match resolve_lorri_root(path) {
-                                        Ok(nix_file) => {
-                                            let desc = format!(
-                                                "{user}'s lorri cache for {}{link_age}",
-                                                nix_file.display()
-                                            );
-                                            Some(desc)
-                                        }
-                                        Err(_) => None,
-                                    }
-                                }
-                                None => None,
-                            },
-                        },
-                    };
-                    match (&link_age, fancy_desc) {
-                        (_, Some(f)) => Cow::Owned(f.into_bytes()),
-                        (LinkAge(Some(_)), None) => {
-                            Cow::Owned(format!("{path_str}{link_age}").into_bytes())
-                        }
-                        _ => Cow::Borrowed(path),
-                    }
+            Link(_) => match self.link_age() {
+                Age(None) => self.label(),
+                link_age => {
+                    let mut res = self.label().into_owned();
+                    res.extend_from_slice(link_age.to_string().as_bytes());
+                    Cow::Owned(res)
                 }
-                Err(_) => Cow::Borrowed(path),
             },
-            Memory(path) | Temporary(path) => Cow::Borrowed(&path),
+            Memory(path) | Temporary(path) | Process(path) | Build(path) | Group(path)
+            | Other(path) => Cow::Borrowed(&path),
             Dummy => Cow::Borrowed(b"{dummy}"),
             FilteredOut => Cow::Borrowed(b"{filtered out}"),
             Transient => Cow::Borrowed(b"{transient}"),
@@ -217,6 +526,70 @@ impl NodeDescription {
         }
     }
 
+    /// like `name`, but without the age of links
+    pub fn label(&self) -> Cow<'_, [u8]> {
+        match self {
+            NodeDescription::Link(path) => {
+                match std::str::from_utf8(path).ok().and_then(classify_link) {
+                    Some((label, _)) => Cow::Owned(label.into_bytes()),
+                    None => Cow::Borrowed(path),
+                }
+            }
+            _ => self.name(),
+        }
+    }
+
+    /// for a link, when it was last changed: usually when the generation or the `result`
+    /// link was created
+    pub fn link_age(&self) -> Age {
+        match self {
+            NodeDescription::Link(path) => Age(sysroot::host_path(path)
+                .symlink_metadata()
+                .and_then(|m| m.modified())
+                .ok()),
+            _ => Age(None),
+        }
+    }
+
+    /// the category of a gc root, like `system` for NixOS generations, or `memory`.
+    /// `None` if this node is not a gc root.
+    pub fn category(&self) -> Option<&'static str> {
+        use self::NodeDescription::*;
+        match self {
+            Link(path) => Some(
+                std::str::from_utf8(path)
+                    .ok()
+                    .and_then(classify_link)
+                    .map_or("other", |(_, category)| category),
+            ),
+            Memory(_) | Process(_) | Transient => Some("memory"),
+            Temporary(_) | Build(_) => Some("build"),
+            Other(_) => Some("unknown"),
+            Group(_) | Path(_) | Shared(_) | Dummy | FilteredOut => None,
+        }
+    }
+
+    /// for a link, whether deleting it would break the running system or a profile in
+    /// use, see `is_current_link`.
+    pub fn is_protected(&self) -> bool {
+        match self {
+            NodeDescription::Link(path) => is_current_link(path),
+            _ => false,
+        }
+    }
+
+    /// for a link, tells why it looks like a forgotten gc root, if it does: its
+    /// directory was deleted, it is in a git checkout unused for `stale_after`, or in a
+    /// temporary directory. Profiles and other known roots are never orphans.
+    pub fn orphan(&self, stale_after: Duration) -> Option<Orphan> {
+        match self {
+            NodeDescription::Link(path) if self.category() == Some("other") => {
+                orphan_status(path, stale_after)
+            }
+            _ => None,
+        }
+    }
+
     /// returns the path as an `OsStr` if this node is on the filesystem
     pub fn path_as_os_str(&self) -> Option<&OsStr> {
         use self::NodeDescription::*;
@@ -229,7 +602,8 @@ impl NodeDescription {
     pub fn path(&self) -> Option<&Path> {
         use self::NodeDescription::*;
         match self {
-            Link(path) | Path(path) | Memory(path) | Temporary(path) => Some(&path),
+            Link(path) | Path(path) | Memory(path) | Temporary(path) | Process(path)
+            | Build(path) | Group(path) | Other(path) => Some(&path),
             Shared(name) => Some(&name),
             Transient | Dummy | FilteredOut => None,
         }
@@ -246,6 +620,10 @@ impl NodeDescription {
             Dummy => NodeKind::Dummy,
             FilteredOut => NodeKind::FilteredOut,
             Transient => NodeKind::Transient,
+            Process(_) => NodeKind::Process,
+            Build(_) => NodeKind::Build,
+            Group(_) => NodeKind::Group,
+            Other(_) => NodeKind::Other,
         }
     }
 }
@@ -261,29 +639,67 @@ impl fmt::Debug for NodeDescription {
     }
 }
 
+/// What nix records about a valid store path, besides its references and size
+///
+/// Not all backends know all fields.
+#[derive(Clone, Debug, Default, Eq, PartialEq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct StorePathInfo {
+    /// the derivation which built this path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deriver: Option<String>,
+    /// when this path was registered in the store, in seconds since the epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registration_time: Option<u64>,
+    /// whether this path is content-addressed
+    #[serde(default)]
+    pub content_addressed: bool,
+    /// whether this path carries at least one signature
+    #[serde(default)]
+    pub signed: bool,
+    /// whether this path was built locally (or added with `nix-store --add`), as opposed
+    /// to substituted
+    #[serde(default)]
+    pub ultimate: bool,
+}
+
+impl StorePathInfo {
+    /// Whether this path was probably downloaded from a binary cache
+    pub fn is_substituted(&self) -> bool {
+        !self.ultimate && (self.signed || self.content_addressed)
+    }
+
+    /// How long ago this path was registered in the store, if known
+    pub fn age(&self) -> Option<Duration> {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(self.registration_time?);
+        time.elapsed().ok()
+    }
+}
+
 #[derive(Clone, Eq, PartialEq, PartialOrd, Ord, Hash)]
 pub struct DepNode {
     pub description: NodeDescription,
     /// size in bytes
     pub size: u64,
+    /// only for store paths, and only when the backend provides it. When nodes are merged,
+    /// the representative keeps its own info, except for `registration_time` which
+    /// becomes the newest of the merged paths.
+    pub info: Option<Box<StorePathInfo>>,
 }
 
-impl DepNode {
-    /// Note: clones the string describing the path.
-    /// # Safety
-    /// `p` must be a valid pointer and contain no null pointer members.
-    /// Its `path` field must contain a valid C string.
-    unsafe fn new(p: &bindings::path_t) -> Self {
-        let path: Vec<u8> = CStr::from_ptr(p.path).to_bytes().to_vec();
+impl NodeDescription {
+    /// Classifies a path as reported by nix: store path, indirect root, in-memory
+    /// root or temporary root.
+    ///
+    /// `is_root` tells whether nix found this path while looking for gc roots.
+    pub fn from_raw(path: Path, is_root: bool) -> Self {
         use self::NodeDescription::*;
-        let description;
-        if path[0] == b'/' {
+        if path.first() == Some(&b'/') {
             if path.starts_with(b"/proc/") {
-                description = Memory(path);
-            } else if p.is_root != 0 {
-                description = Link(path);
+                Memory(path)
+            } else if is_root {
+                Link(path)
             } else {
-                description = Path(path);
+                Path(path)
             }
         // {memory} is nix < 2.2 and was replaced by paths in /proc for linux and {lsof} for darwin in nix 2.3.
         // See https://github.com/NixOS/nix/commit/a3f37d87eabcfb5dc581abcfa46e5e7d387dfa8c
@@ -301,25 +717,34 @@ impl DepNode {
             || path.starts_with(b"{procstat:")
             || path.starts_with(b"{{sysctl:")
         {
-            description = Memory(path);
+            Memory(path)
         } else if path.starts_with(b"{temp:") {
-            description = Temporary(path);
+            Temporary(path)
         } else {
-            panic!(
-                "Unknown store path type: {}",
-                String::from_utf8_lossy(&path)
-            );
-        }
-        Self {
-            description,
-            size: p.size,
+            // new versions of nix and lix regularly introduce new kinds of roots: warn
+            // once per kind, as told by what comes before the first colon
+            lazy_static! {
+                static ref WARNED: Mutex<collections::HashSet<Vec<u8>>> =
+                    Mutex::new(collections::HashSet::new());
+            };
+            let kind = path.split(|&c| c == b':').next().unwrap_or_default();
+            if WARNED.lock().unwrap().insert(kind.to_vec()) {
+                msg!(
+                    "warning: unknown kind of gc root {}, shown as is. Please report it to nix-du.\n",
+                    String::from_utf8_lossy(&path)
+                )
+            }
+            Other(path)
         }
     }
+}
 
+impl DepNode {
     pub fn dummy() -> Self {
         DepNode {
             description: NodeDescription::Dummy,
             size: 0,
+            info: None,
         }
     }
 
@@ -330,6 +755,21 @@ impl DepNode {
     pub fn name(&self) -> Cow<'_, [u8]> {
         self.description.name()
     }
+
+    /// When this path, or the newest of the paths merged into this node, was registered
+    pub fn registration_time(&self) -> Option<u64> {
+        self.info.as_ref()?.registration_time
+    }
+
+    /// Records that a path registered at `time` was merged into this node.
+    pub fn merge_registration_time(&mut self, time: Option<u64>) {
+        let time = match time {
+            Some(t) => t,
+            None => return,
+        };
+        let info = self.info.get_or_insert_with(Default::default);
+        info.registration_time = Some(info.registration_time.map_or(time, |t| t.max(time)));
+    }
 }
 
 impl fmt::Debug for DepNode {
@@ -339,20 +779,22 @@ impl fmt::Debug for DepNode {
 }
 
 /// Whether all nodes are reachable from the root
-#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Reachability {
     Connected,
     Disconnected,
 }
 
 /// Whether deduplicated nodes are counted several times
-#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum DedupAwareness {
     Aware,
     Unaware,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SizeMetadata {
     pub reachable: Reachability,
     pub dedup: DedupAwareness,
@@ -370,57 +812,176 @@ pub struct DepInfos {
     pub metadata: SizeMetadata,
 }
 
+/// What the C++ side registers through `register_node` and `register_edge`
+#[derive(Default)]
+pub struct FfiSink {
+    store: MemoryStore,
+    /// for each node index of the C++ side, the index in `store` for store paths, or the
+    /// description of the root for gc roots
+    nodes: Vec<Result<usize, Path>>,
+    roots: Vec<Root>,
+}
+
 // symbol exported to libnix_adapter
 /// # Safety
 /// `g` must have been obtained by rust code, and not modified by C code.
-/// `p` must be a valid pointer and contain no null pointer members.
-/// Its `path` field must contain a valid C string.
+/// `p` must be a valid pointer.
+/// Its `path` field must contain a valid C string, and its `deriver` field must be null
+/// or contain a valid C string.
 #[no_mangle]
-pub unsafe extern "C" fn register_node(g: *mut DepGraph, p: *const bindings::path_t) {
+pub unsafe extern "C" fn register_node(g: *mut FfiSink, p: *const bindings::path_t) {
     let p: &bindings::path_t = p.as_ref().unwrap();
-    let g: &mut DepGraph = g.as_mut().unwrap();
-    let drv = DepNode::new(p);
-    g.add_node(drv);
+    let g: &mut FfiSink = g.as_mut().unwrap();
+    let path: Vec<u8> = CStr::from_ptr(p.path).to_bytes().to_vec();
+    let node = if p.is_root != 0 {
+        Err(path)
+    } else {
+        let deriver = if p.deriver.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(p.deriver).to_string_lossy().into_owned())
+        };
+        let idx = g.store.add_path(path, p.size);
+        g.store.set_info(
+            idx,
+            StorePathInfo {
+                deriver,
+                registration_time: u64::try_from(p.registration_time).ok().filter(|&t| t > 0),
+                content_addressed: p.content_addressed != 0,
+                signed: p.has_signatures != 0,
+                ultimate: p.ultimate != 0,
+            },
+        );
+        Ok(idx)
+    };
+    g.nodes.push(node);
 }
 
 // symbol exported to libnix_adapter
 /// # Safety
 /// `g` must have been obtained by rust code, and not modified by C code.
 #[no_mangle]
-pub unsafe extern "C" fn register_edge(g: *mut DepGraph, from: u32, to: u32) {
-    if from == to {
-        return;
+pub unsafe extern "C" fn register_edge(g: *mut FfiSink, from: u32, to: u32) {
+    let g: &mut FfiSink = g.as_mut().unwrap();
+    match (&g.nodes[from as usize], &g.nodes[to as usize]) {
+        (Ok(from), Ok(to)) => g.store.add_reference(*from, *to),
+        (Err(link), Ok(to)) => {
+            let target = g.store.path(*to).clone();
+            g.roots.push((link.clone(), target));
+        }
+        (_, Err(_)) => panic!("edge to a gc root"),
     }
-    let g: &mut DepGraph = g.as_mut().unwrap();
-    g.add_edge(NodeIndex::from(from), NodeIndex::from(to), ());
 }
 
-impl DepInfos {
-    /// returns the dependency graph of the nix-store
-    /// actual connection specifics are left to libnixstore
-    /// (reading ourselves, connecting to a daemon...)
-    pub fn read_from_store(root: Option<OsString>) -> Result<Self, i32> {
-        let mut g = DepGraph::new();
-        let gptr = &mut g as *mut _ as *mut c_void;
-        let root_data = root.map(|path| {
-            let mut bytes = path.into_vec();
-            bytes.push(0);
-            bytes
-        });
-        let rootptr: *const u8 = match root_data.as_ref() {
+/// The store as seen by libnixstore
+/// actual connection specifics are left to libnixstore
+/// (reading ourselves, connecting to a daemon...)
+#[derive(Debug, Clone, Default)]
+pub struct LibNixStore {
+    /// if not empty, only read the closure of these paths. Each root is a pair of its
+    /// name and its path, which must not contain null bytes.
+    pub roots: Vec<Root>,
+    /// the uri of the store to open, as in `nix --store`. If `None`, the store is chosen
+    /// by libnixstore (`NIX_REMOTE`, `nix.conf`...).
+    pub store: Option<String>,
+}
+
+/// A failure of libnixstore, with the exit code it chose
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LibNixStoreError(pub i32);
+
+impl Display for LibNixStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "libnixstore failed with code {}", self.0)
+    }
+}
+
+impl std::error::Error for LibNixStoreError {}
+
+impl StoreBackend for LibNixStore {
+    fn contents(self) -> std::io::Result<MemoryStore> {
+        let mut sink = FfiSink::default();
+        let gptr = &mut sink as *mut _ as *mut c_void;
+        let root_data: Vec<CString> = self
+            .roots
+            .iter()
+            .map(|(_, path)| CString::new(path.clone()).expect("root contains a null byte"))
+            .collect();
+        // null terminated array of pointers to root_data
+        let root_ptrs: Vec<*const c_char> = root_data
+            .iter()
+            .map(|path| path.as_ptr())
+            .chain(std::iter::once(std::ptr::null()))
+            .collect();
+        let rootptr: *const *const c_char = if root_data.is_empty() {
+            std::ptr::null()
+        } else {
+            root_ptrs.as_ptr()
+        };
+        let store_data = self
+            .store
+            .map(|uri| CString::new(uri).expect("store uri contains a null byte"));
+        let storeptr = match store_data.as_ref() {
             None => std::ptr::null(),
-            Some(path) => path.as_ptr(),
+            Some(uri) => uri.as_ptr(),
         };
-        let res = unsafe { bindings::populateGraph(gptr, rootptr as *const std::os::raw::c_char) };
+        let res = unsafe { bindings::populateGraph(gptr, rootptr, storeptr) };
 
         if res != 0 {
-            return Err(res);
+            return Err(std::io::Error::other(LibNixStoreError(res)));
         }
-        let root_idx = match &root_data {
-            None => g.add_node(DepNode::dummy()),
-            Some(_) => NodeIndex::from(0),
+        let FfiSink {
+            mut store, roots, ..
+        } = sink;
+        if self.roots.is_empty() {
+            store.add_gc_roots(roots);
+        } else {
+            // requested roots are registered in order
+            let closure = self
+                .roots
+                .into_iter()
+                .zip(roots)
+                .map(|((name, _), (_, target))| {
+                    (
+                        name,
+                        store.index_of(&target).expect("root to unregistered path"),
+                    )
+                })
+                .collect();
+            store.roots = Roots::Closure(closure);
+        }
+        Ok(store)
+    }
+}
+
+impl DepInfos {
+    /// Wraps a freshly read dependency graph.
+    ///
+    /// If `root` is `None`, the graph is considered to be the whole store: a dummy root
+    /// is added, with an edge to every gc root.
+    /// Otherwise all nodes are expected to be reachable from `root`.
+    pub fn from_graph(mut g: DepGraph, root: Option<NodeIndex>) -> Self {
+        let root_idx = match root {
+            Some(idx) => idx,
+            None => {
+                let gc_roots: Vec<_> = g
+                    .node_references()
+                    .filter_map(|(idx, n)| {
+                        if n.kind().is_gc_root() {
+                            Some(idx)
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+                let idx = g.add_node(DepNode::dummy());
+                for root in gc_roots {
+                    g.add_edge(idx, root, ());
+                }
+                idx
+            }
         };
-        let reachable = match &root_data {
+        let reachable = match root {
             None => Reachability::Disconnected,
             Some(_) => Reachability::Connected,
         };
@@ -434,24 +995,8 @@ impl DepInfos {
             graph: g,
             metadata,
         };
-        if root_data.is_none() {
-            let gc_roots: Vec<_> = di
-                .graph
-                .node_references()
-                .filter_map(|(idx, n)| {
-                    if n.kind().is_gc_root() {
-                        Some(idx)
-                    } else {
-                        None
-                    }
-                })
-                .collect();
-            for root in gc_roots {
-                di.graph.add_edge(di.root, root, ());
-            }
-        }
         di.record_metadata();
-        Ok(di)
+        di
     }
 
     /// returns the sum of the size of all the derivations reachable from the root
@@ -469,6 +1014,72 @@ impl DepInfos {
         self.graph.raw_nodes().iter().map(|n| n.weight.size).sum()
     }
 
+    /// returns the set of nodes reachable from `start` without going through `removed`,
+    /// that is, with `start` as root, what would stay alive if the nodes in `removed`
+    /// were deleted.
+    pub fn reachable_without(&self, start: NodeIndex, removed: &FixedBitSet) -> FixedBitSet {
+        let filtered =
+            NodeFiltered::from_fn(&self.graph, |idx: NodeIndex| !removed.contains(idx.index()));
+        let mut dfs = Dfs::new(&filtered, start);
+        while dfs.next(&filtered).is_some() {}
+        dfs.discovered
+    }
+
+    /// returns, for each node, the size of the nodes which would no longer be reachable
+    /// from the root if this node were removed, including itself.
+    ///
+    /// Requires that all nodes are reachable from the root.
+    pub fn exclusive_sizes(&self) -> Vec<u64> {
+        assert_eq!(self.metadata.reachable, Reachability::Connected);
+        dominated_sizes(&self.graph, self.root, |idx| self.graph[idx].size)
+    }
+
+    /// like `exclusive_sizes`, for sets of nodes: returns, for each of `sets`, the size of
+    /// the nodes which would no longer be reachable from the root if all the nodes of the
+    /// set were removed, including themselves.
+    ///
+    /// Sets must be disjoint. Requires that all nodes are reachable from the root.
+    pub fn exclusive_sizes_of(&self, sets: &[Vec<NodeIndex>]) -> Vec<u64> {
+        assert_eq!(self.metadata.reachable, Reachability::Connected);
+        // each set becomes a node of its own, through which all the references to its
+        // members go: it dominates exactly what removing the whole set frees
+        let n = self.graph.node_count();
+        let mut set_of = vec![None; n];
+        for (i, set) in sets.iter().enumerate() {
+            for idx in set {
+                set_of[idx.index()] = Some(NodeIndex::new(n + i));
+            }
+        }
+        let mut graph =
+            petgraph::Graph::<u64, ()>::with_capacity(n + sets.len(), self.graph.edge_count() + n);
+        for node in self.graph.raw_nodes() {
+            graph.add_node(node.weight.size);
+        }
+        for _ in sets {
+            graph.add_node(0);
+        }
+        for edge in self.graph.raw_edges() {
+            let target = set_of[edge.target().index()].unwrap_or_else(|| edge.target());
+            graph.add_edge(edge.source(), target, ());
+        }
+        for (i, set) in sets.iter().enumerate() {
+            for &idx in set {
+                graph.add_edge(NodeIndex::new(n + i), idx, ());
+            }
+        }
+        let sizes = dominated_sizes(&graph, self.root, |idx| graph[idx]);
+        sets.iter()
+            .enumerate()
+            .map(|(i, set)| {
+                if set.contains(&self.root) {
+                    self.size()
+                } else {
+                    sizes[n + i]
+                }
+            })
+            .collect()
+    }
+
     /// records the current size of the graph in its metadata field.
     pub fn record_metadata(&mut self) {
         let dedup = self.metadata.dedup;
@@ -531,3 +1142,223 @@ impl DepInfos {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn ages() {
+        assert_eq!(parse_age("90d"), Ok(DAY * 90));
+        assert_eq!(parse_age("2w"), Ok(DAY * 14));
+        assert_eq!(parse_age("1y"), Ok(YEAR));
+        assert_eq!(parse_age("0h"), Ok(Duration::from_secs(0)));
+        assert!(parse_age("90").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("3 days").is_err());
+        let week_ago = SystemTime::now() - DAY * 7;
+        assert_eq!(Age(Some(week_ago)).to_string(), ", 7d ago");
+        assert_eq!(Age(None).to_string(), "");
+    }
+
+    #[test]
+    fn orphans() {
+        let tmp = TempDir::new("orphans");
+        let checkout = tmp.join("checkout");
+        std::fs::create_dir_all(checkout.join(".git")).unwrap();
+        std::fs::write(checkout.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+        let long_ago = SystemTime::now() - DAY * 200;
+        for file in &[".git/HEAD", ".git"] {
+            std::fs::File::open(checkout.join(file))
+                .unwrap()
+                .set_modified(long_ago)
+                .unwrap();
+        }
+        std::os::unix::fs::symlink("/nix/store/aaaa-foo", checkout.join("result")).unwrap();
+        std::os::unix::fs::symlink("/nix/store/bbbb-bar", tmp.join("result")).unwrap();
+        let orphan = |path: &std::path::Path, stale_after| {
+            NodeDescription::Link(path.as_os_str().as_bytes().to_vec()).orphan(stale_after)
+        };
+        let stale = orphan(&checkout.join("result"), DAY * 90);
+        let recent = orphan(&checkout.join("result"), YEAR);
+        let in_tmp = orphan(&tmp.join("result"), DAY * 90);
+        let gone = orphan(&tmp.join("gone/result"), DAY * 90);
+
+        match stale {
+            Some(Orphan::StaleCheckout(dir, _)) => assert_eq!(dir, checkout),
+            other => panic!("expected a stale checkout, got {:?}", other),
+        }
+        let expected = if tmp.starts_with("/tmp") {
+            Some(Orphan::Temporary)
+        } else {
+            None
+        };
+        assert_eq!(recent, expected);
+        assert_eq!(in_tmp, expected);
+        assert_eq!(gone, Some(Orphan::Gone));
+        let profile = NodeDescription::Link(b"/nix/var/nix/profiles/system-3-link".to_vec());
+        assert_eq!(profile.orphan(DAY), None);
+    }
+
+    #[test]
+    fn unknown_roots() {
+        let description = NodeDescription::from_raw(b"{kvm:12}".to_vec(), true);
+        assert_eq!(description.kind(), NodeKind::Other);
+        assert!(description.kind().is_gc_root());
+        assert_eq!(&*description.name(), &b"{kvm:12}"[..]);
+        assert_eq!(
+            NodeDescription::from_raw(b"{temp:12}".to_vec(), true).kind(),
+            NodeKind::Temporary
+        );
+        // a line ` -> /nix/store/...` of `nix-store --gc --print-roots`
+        assert_eq!(
+            NodeDescription::from_raw(Vec::new(), true).kind(),
+            NodeKind::Other
+        );
+    }
+
+    #[test]
+    fn protected_links() {
+        let tmp = TempDir::new("protected");
+        std::fs::create_dir_all(tmp.join("store/aaaa-profile")).unwrap();
+        std::fs::create_dir_all(tmp.join("store/bbbb-profile")).unwrap();
+        let link = |target: &str, name: &str| {
+            std::os::unix::fs::symlink(target, tmp.join(name)).unwrap();
+            NodeDescription::Link(tmp.join(name).as_os_str().as_bytes().to_vec())
+        };
+        let old = link("store/aaaa-profile", "profile-1-link");
+        let current = link("store/bbbb-profile", "profile-2-link");
+        link("profile-2-link", "profile");
+        let result = link("store/bbbb-profile", "result");
+        let (old, current, result) = (
+            old.is_protected(),
+            current.is_protected(),
+            result.is_protected(),
+        );
+        assert!(!old);
+        assert!(current);
+        assert!(!result);
+        assert!(NodeDescription::Link(b"/run/booted-system".to_vec()).is_protected());
+        assert!(!NodeDescription::Memory(b"/proc/1/maps".to_vec()).is_protected());
+    }
+
+    #[test]
+    fn names_and_versions() {
+        let split = |name: &str| {
+            let (pname, version) = split_name(name.as_bytes());
+            (
+                String::from_utf8(pname.to_vec()).unwrap(),
+                String::from_utf8(version.to_vec()).unwrap(),
+            )
+        };
+        let owned = |a: &str, b: &str| (a.to_string(), b.to_string());
+        assert_eq!(split("python3-3.11.6"), owned("python3", "3.11.6"));
+        assert_eq!(split("python3-3.11.6-dev"), owned("python3", "3.11.6-dev"));
+        assert_eq!(split("gnome-shell-45.2"), owned("gnome-shell", "45.2"));
+        assert_eq!(split("llvm-project-17"), owned("llvm-project", "17"));
+        assert_eq!(split("source"), owned("source", ""));
+        assert_eq!(split("hello-"), owned("hello-", ""));
+    }
+
+    #[test]
+    fn nixos_generation() {
+        use std::os::unix::fs::symlink;
+        let tmp = TempDir::new("generation");
+        let store = tmp.join("nix/store");
+        let system = store.join("aaaa-nixos-system-foo-24.05.20240601.abcdef");
+        let linux = store.join("bbbb-linux-6.6.32");
+        let path = store.join("cccc-system-path");
+        let tools = store.join("dddd-nixos-version");
+        for dir in &[&system, &linux, &path.join("bin"), &tools.join("bin")] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        std::fs::write(system.join("nixos-version"), "24.05.20240601.abcdef\n").unwrap();
+        std::fs::write(linux.join("bzImage"), "").unwrap();
+        symlink(linux.join("bzImage"), system.join("kernel")).unwrap();
+        symlink(&path, system.join("sw")).unwrap();
+        std::fs::write(
+            tools.join("bin/nixos-version"),
+            r#"echo '{"configurationRevision":"0123abc","nixosVersion":"24.05"}'"#,
+        )
+        .unwrap();
+        symlink(
+            tools.join("bin/nixos-version"),
+            path.join("bin/nixos-version"),
+        )
+        .unwrap();
+        let details = nixos_generation_details(&system);
+        let missing = nixos_generation_details(&tmp.join("missing"));
+        assert_eq!(
+            details,
+            vec!["24.05.20240601.abcdef", "linux 6.6.32", "rev 0123abc"]
+        );
+        assert!(missing.is_empty());
+    }
+
+    #[test]
+    fn link_names() {
+        // these links do not exist, so their age is not shown
+        let cases = [
+            (
+                "/nix/var/nix/profiles/per-user/alice/profile-12-link",
+                "generation 12 of alice's profile",
+            ),
+            (
+                "/nix/var/nix/profiles/per-user/alice/channels-3-link",
+                "generation 3 of alice's profile channels",
+            ),
+            (
+                "/home/alice/.local/state/nix/profiles/profile-4-link",
+                "generation 4 of alice's profile",
+            ),
+            (
+                "/root/.local/state/nix/profiles/home-manager-7-link",
+                "home-manager generation 7 of root",
+            ),
+            (
+                "/nix/var/nix/profiles/per-user/alice/home-manager-8-link",
+                "home-manager generation 8 of alice",
+            ),
+            (
+                "/home/alice/.local/state/home-manager/gcroots/current-home",
+                "alice's current home-manager generation",
+            ),
+            (
+                "/nix/var/nix/gcroots/per-user/alice/current-home",
+                "alice's current home-manager generation",
+            ),
+            ("/nix/var/nix/profiles/system-42-link", "NixOS generation 42"),
+            (
+                "/home/alice/src/foo/.direnv/flake-profile-a5d5b61aa8a61b7d9d765e1daf971a9a3f22b9d0",
+                "nix-direnv environment of /home/alice/src/foo",
+            ),
+            (
+                "/home/alice/src/foo/.direnv/flake-inputs/0123abcd-source",
+                "nix-direnv flake inputs of /home/alice/src/foo",
+            ),
+            (
+                "/home/alice/src/bar/.devenv/gc/shell",
+                "devenv environment of /home/alice/src/bar",
+            ),
+            ("/home/alice/result", "/home/alice/result"),
+        ];
+        for &(link, name) in &cases {
+            let description = NodeDescription::Link(link.as_bytes().to_vec());
+            assert_eq!(String::from_utf8_lossy(&description.name()), name);
+        }
+        let category = |link: &str| NodeDescription::Link(link.as_bytes().to_vec()).category();
+        assert_eq!(category(cases[2].0), Some("user profile"));
+        assert_eq!(category(cases[3].0), Some("home-manager"));
+        assert_eq!(category(cases[7].0), Some("system"));
+        assert_eq!(category(cases[11].0), Some("other"));
+        assert_eq!(
+            NodeDescription::Temporary(b"{temp:1}".to_vec()).category(),
+            Some("build")
+        );
+        assert_eq!(
+            NodeDescription::Path(b"/nix/store/aaaa-foo".to_vec()).category(),
+            None
+        );
+    }
+}
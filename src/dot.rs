@@ -7,19 +7,54 @@ use scarlet::colormap::ColorMap;
 use scarlet::material_colors::MaterialPrimary;
 use scarlet::{colormap::ListedColorMap, prelude::*};
 use std::io::{self, Write};
+use std::time::{Duration, SystemTime};
 
-pub fn render<W: Write>(dependencies: &depgraph::DepInfos, w: &mut W) -> io::Result<()> {
+/// What the colour of nodes represents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coloring {
+    /// from blue for the lightest nodes to red for the heaviest
+    Size,
+    /// from blue for the most recently registered nodes to red for the oldest; grey when
+    /// the registration time is unknown
+    Age,
+}
+
+/// Writes `text` so that it can appear between double quotes in the dot language.
+fn write_escaped<W: Write>(w: &mut W, text: &[u8]) -> io::Result<()> {
+    for chunk in text.split_inclusive(|&c| c == b'"' || c == b'\\') {
+        match chunk.split_last() {
+            Some((&c, rest)) if c == b'"' || c == b'\\' => {
+                w.write_all(rest)?;
+                w.write_all(&[b'\\', c])?;
+            }
+            _ => w.write_all(chunk)?,
+        }
+    }
+    Ok(())
+}
+
+pub fn render<W: Write>(
+    dependencies: &depgraph::DepInfos,
+    coloring: Coloring,
+    w: &mut W,
+) -> io::Result<()> {
     // compute color gradient
     // first, min and max
-    let mut min = dependencies.graph.raw_nodes()[0].weight.size;
-    let mut max = min;
-    for node in &dependencies.graph.raw_nodes()[1..] {
-        max = std::cmp::max(node.weight.size, max);
-        min = std::cmp::min(node.weight.size, min);
-    }
-    let span = (max - min) as f64;
+    let value = |node: &depgraph::DepNode| match coloring {
+        Coloring::Size => Some(node.size),
+        // older is higher
+        Coloring::Age => node.registration_time().map(|t| u64::MAX - t),
+    };
+    let values = dependencies
+        .graph
+        .raw_nodes()
+        .iter()
+        .filter_map(|n| value(&n.weight));
+    let min = values.clone().min().unwrap_or(0);
+    let max = values.max().unwrap_or(0);
+    let span = std::cmp::max(max - min, 1) as f64;
 
-    let scale = move |size| ((size - min) as f64) / span;
+    let scale = move |value| ((value - min) as f64) / span;
 
     let gradient = ListedColorMap::turbo();
     let textcolors: Vec<RGBColor> = [MaterialPrimary::White, MaterialPrimary::Black]
@@ -41,10 +76,12 @@ pub fn render<W: Write>(dependencies: &depgraph::DepInfos, w: &mut W) -> io::Res
             continue;
         };
         let size = ByteSize::b(node.size);
-        let offset = scale(node.size);
-        // make large node more visible in the color map
-        let offset = offset.sqrt();
-        let color: RGBColor = gradient.transform_single(offset);
+        let color: RGBColor = match (coloring, value(node)) {
+            // make large node more visible in the color map
+            (Coloring::Size, Some(value)) => gradient.transform_single(scale(value).sqrt()),
+            (_, Some(value)) => gradient.transform_single(scale(value)),
+            (_, None) => RGBColor::from_material_palette(MaterialPrimary::Grey),
+        };
         let textcolor = textcolors
             .iter()
             .max_by_key(|c| (c.distance(&color) * 1000.) as u64)
@@ -52,13 +89,30 @@ pub fn render<W: Write>(dependencies: &depgraph::DepInfos, w: &mut W) -> io::Res
             .to_string();
         write!(
             w,
-            "N{}[color=\"{}\",fontcolor=\"{}\",label=\"",
+            "N{}[color=\"{}\",fontcolor=\"{}\",",
             idx.index(),
             color.to_string(),
             textcolor
         )?;
-        w.write_all(&node.name())?;
-        writeln!(w, " ({})\"];", size)?;
+        if let Some(category) = node.description.category() {
+            w.write_all(b"tooltip=\"")?;
+            write_escaped(w, category.as_bytes())?;
+            w.write_all(b"\",")?;
+        }
+        if node.kind() == depgraph::NodeKind::Build {
+            // in-flight builds are not there for long
+            w.write_all(b"style=\"filled,dashed\",")?;
+        }
+        w.write_all(b"label=\"")?;
+        write_escaped(w, &node.name())?;
+        write!(w, " ({}", size)?;
+        if coloring == Coloring::Age {
+            let registered = node
+                .registration_time()
+                .map(|t| SystemTime::UNIX_EPOCH + Duration::from_secs(t));
+            write!(w, "{}", depgraph::Age(registered))?;
+        }
+        writeln!(w, ")\"];")?;
     }
     for edge in dependencies.graph.raw_edges() {
         if edge.source() == dependencies.root {
@@ -74,3 +128,15 @@ pub fn render<W: Write>(dependencies: &depgraph::DepInfos, w: &mut W) -> io::Res
     w.write_all(b"}\n")?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaping() {
+        let mut out = Vec::new();
+        write_escaped(&mut out, br#"a "quoted" C:\path\"#).unwrap();
+        assert_eq!(out, br#"a \"quoted\" C:\\path\\"#.to_vec());
+    }
+}
@@ -0,0 +1,553 @@
+// SPDX-License-Identifier: LGPL-3.0
+
+//! Discovery of gc roots without libnixstore, for the backends which do not go through
+//! `bindings::populateGraph`.
+
+use crate::depgraph::Path;
+use crate::sysroot;
+use lazy_static::lazy_static;
+use std::ffi::OsStr;
+use std::io::{self, BufRead};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use walkdir::WalkDir;
+
+/// A gc root: the path of the link (or the censored description of the root), and the
+/// store path it keeps alive.
+pub type Root = (Path, Path);
+
+/// Parses the output of `nix-store --gc --print-roots`: lines of the form
+/// `/path/to/link -> /nix/store/hash-name`. A line consisting only of a store path
+/// makes this store path a root by itself.
+pub fn parse_print_roots<R: BufRead>(roots: R) -> io::Result<Vec<Root>> {
+    let mut res = Vec::new();
+    for line in roots.split(b'\n') {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let sep = b" -> ";
+        match line.windows(sep.len()).rposition(|w| w == sep) {
+            Some(i) => res.push((line[..i].to_vec(), line[i + sep.len()..].to_vec())),
+            None => res.push((line.clone(), line)),
+        }
+    }
+    Ok(res)
+}
+
+/// If `path` is inside the store, returns the store path containing it.
+pub fn to_store_path(path: &[u8], store_dir: &[u8]) -> Option<Path> {
+    if !(path.starts_with(store_dir) && path.get(store_dir.len()) == Some(&b'/')) {
+        return None;
+    }
+    let name = &path[store_dir.len() + 1..];
+    let name = match name.iter().position(|&c| c == b'/') {
+        Some(i) => &name[..i],
+        None => name,
+    };
+    if name.is_empty() {
+        return None;
+    }
+    let mut res = store_dir.to_vec();
+    res.push(b'/');
+    res.extend_from_slice(name);
+    Some(res)
+}
+
+/// Whether this error is the kind of errors nix ignores when looking for roots
+fn is_permanent(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::PermissionDenied | io::ErrorKind::NotFound
+    ) || e.raw_os_error() == Some(20 /* ENOTDIR */)
+}
+
+/// Recursively finds roots in `dir`, the same way `LocalStore::findRoots` does:
+/// * symlinks to the store are roots
+/// * symlinks elsewhere are indirect roots: if they point to a symlink to the store,
+///   then the latter is a root
+/// * regular files named after a store path make this store path a root.
+///
+/// `dir` and the returned links are paths of the system mounted at `system_root`, and
+/// absolute symlinks are resolved relative to it.
+///
+/// Unlike nix, stale indirect roots are not removed.
+fn find_roots_in(
+    system_root: Option<&std::path::Path>,
+    dir: &std::path::Path,
+    store_dir: &[u8],
+    roots: &mut Vec<Root>,
+) -> io::Result<()> {
+    let host = |path: &std::path::Path| sysroot::rebase(system_root, path);
+    let host_dir = host(dir);
+    for entry in WalkDir::new(&host_dir).follow_links(false) {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(e) => match e.io_error() {
+                Some(ioe) if is_permanent(ioe) => continue,
+                _ => return Err(e.into()),
+            },
+        };
+        let host_path = entry.path();
+        let path = dir.join(
+            host_path
+                .strip_prefix(&host_dir)
+                .expect("walkdir escaped its root"),
+        );
+        let ty = entry.file_type();
+        if ty.is_symlink() {
+            let target = match std::fs::read_link(host_path) {
+                Ok(t) => t,
+                Err(ref e) if is_permanent(e) => continue,
+                Err(e) => return Err(e),
+            };
+            if let Some(store_path) = to_store_path(target.as_os_str().as_bytes(), store_dir) {
+                roots.push((path.as_os_str().as_bytes().to_vec(), store_path));
+                continue;
+            }
+            // indirect root
+            let target = match path.parent() {
+                Some(parent) => parent.join(target),
+                None => target,
+            };
+            // the directory of the indirect root may be reached through absolute symlinks
+            // of the analysed system
+            let link = match (target.parent(), target.file_name()) {
+                (Some(parent), Some(name)) => match sysroot::canonicalize_in(system_root, parent) {
+                    Ok(parent) => host(&parent.join(name)),
+                    Err(ref e) if is_permanent(e) => continue,
+                    Err(e) => return Err(e),
+                },
+                _ => host(&target),
+            };
+            match link.symlink_metadata() {
+                Ok(m) if m.file_type().is_symlink() => (),
+                _ => continue,
+            }
+            let target2 = match std::fs::read_link(&link) {
+                Ok(t) => t,
+                Err(ref e) if is_permanent(e) => continue,
+                Err(e) => return Err(e),
+            };
+            if let Some(store_path) = to_store_path(target2.as_os_str().as_bytes(), store_dir) {
+                roots.push((target.as_os_str().as_bytes().to_vec(), store_path));
+            }
+        } else if ty.is_file() {
+            let mut candidate = store_dir.to_vec();
+            candidate.push(b'/');
+            candidate.extend_from_slice(entry.file_name().as_bytes());
+            roots.push((path.as_os_str().as_bytes().to_vec(), candidate));
+        }
+    }
+    Ok(())
+}
+
+/// Temporary roots registered by running nix processes in `state_dir/temproots`
+fn find_temp_roots(state_dir: &std::path::Path, roots: &mut Vec<Root>) -> io::Result<()> {
+    let dir = match state_dir.join("temproots").read_dir() {
+        Ok(d) => d,
+        Err(ref e) if is_permanent(e) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let proc_exists = std::path::Path::new("/proc/self").exists();
+    for entry in dir {
+        let entry = entry?;
+        let pid = entry.file_name();
+        // the process holding these roots is dead
+        if proc_exists && !std::path::Path::new("/proc").join(&pid).exists() {
+            continue;
+        }
+        let content = match std::fs::read(entry.path()) {
+            Ok(c) => c,
+            Err(ref e) if is_permanent(e) => continue,
+            Err(e) => return Err(e),
+        };
+        let mut name = b"{temp:".to_vec();
+        name.extend_from_slice(pid.as_bytes());
+        name.push(b'}');
+        for path in content.split(|&c| c == 0).filter(|p| !p.is_empty()) {
+            roots.push((name.clone(), path.to_vec()));
+        }
+    }
+    Ok(())
+}
+
+/// Roots held by running processes, found by scanning `/proc`.
+///
+/// This is a subset of what nix does: executables, working directories, open files,
+/// memory maps and environment variables.
+fn find_runtime_roots(store_dir: &[u8], roots: &mut Vec<Root>) {
+    lazy_static! {
+        static ref STORE_PATH_NAME: regex::bytes::Regex =
+            regex::bytes::Regex::new(r"/[0-9a-z]{32}-[-.+_?=0-9a-zA-Z]+")
+                .expect("regex compilation failed");
+    };
+    let procs = match std::path::Path::new("/proc").read_dir() {
+        Ok(p) => p,
+        Err(_) => return,
+    };
+    let link_root = |link: &std::path::Path, roots: &mut Vec<Root>| {
+        if let Ok(target) = std::fs::read_link(link) {
+            if let Some(p) = to_store_path(target.as_os_str().as_bytes(), store_dir) {
+                roots.push((link.as_os_str().as_bytes().to_vec(), p));
+            }
+        }
+    };
+    for entry in procs.flatten() {
+        let pid = entry.file_name();
+        if !pid.as_bytes().iter().all(u8::is_ascii_digit) {
+            continue;
+        }
+        let dir = entry.path();
+        link_root(&dir.join("exe"), roots);
+        link_root(&dir.join("cwd"), roots);
+        if let Ok(fds) = dir.join("fd").read_dir() {
+            for fd in fds.flatten() {
+                link_root(&fd.path(), roots);
+            }
+        }
+        for file in &["maps", "environ"] {
+            let path = dir.join(file);
+            if let Ok(content) = std::fs::read(&path) {
+                for m in STORE_PATH_NAME.find_iter(&content) {
+                    let start = m.start();
+                    if content[..start].ends_with(store_dir) {
+                        let mut p = store_dir.to_vec();
+                        p.extend_from_slice(m.as_bytes());
+                        roots.push((path.as_os_str().as_bytes().to_vec(), p));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The pid of the process holding an in-memory root like `/proc/1234/maps`, or a
+/// temporary root like `{temp:1234}`
+pub fn pid_of(root: &[u8]) -> Option<u32> {
+    let pid = match root.strip_prefix(b"{temp:") {
+        Some(rest) => rest.strip_suffix(b"}")?,
+        None => {
+            let rest = root.strip_prefix(b"/proc/")?;
+            let end = rest.iter().position(|&c| c == b'/').unwrap_or(rest.len());
+            &rest[..end]
+        }
+    };
+    std::str::from_utf8(pid).ok()?.parse().ok()
+}
+
+/// A label for a running process, like `firefox (pid 1234)`, from `/proc/PID/comm` and
+/// `/proc/PID/cmdline`. Only the pid remains if the process is gone.
+pub fn process_label(pid: u32) -> Path {
+    let dir = std::path::Path::new("/proc").join(pid.to_string());
+    let comm = std::fs::read(dir.join("comm")).ok().map(|mut comm| {
+        if comm.last() == Some(&b'\n') {
+            comm.pop();
+        }
+        comm
+    });
+    let argv0 = std::fs::read(dir.join("cmdline")).ok().and_then(|cmdline| {
+        let argv0 = cmdline.split(|&c| c == 0).next()?;
+        let name = argv0.rsplit(|&c| c == b'/').next()?;
+        Some(name.to_vec())
+    });
+    // comm is truncated to 15 bytes, argv0 is not but can be changed by the process
+    let name = match (comm, argv0) {
+        (Some(comm), Some(argv0)) if argv0.starts_with(&comm) => Some(argv0),
+        (Some(comm), _) if !comm.is_empty() => Some(comm),
+        (_, argv0) => argv0.filter(|a| !a.is_empty()),
+    };
+    match name {
+        Some(mut name) => {
+            name.extend_from_slice(format!(" (pid {pid})").as_bytes());
+            name
+        }
+        None => format!("pid {pid}").into_bytes(),
+    }
+}
+
+/// The uid owning a gc root: the owner of the symlink, or of the process holding an
+/// in-memory or temporary root. `None` if it cannot be known, for example when the roots
+/// were not found on this machine.
+pub fn owner_of(root: &[u8]) -> Option<u32> {
+    if !sysroot::is_available() {
+        return None;
+    }
+    let path = match pid_of(root) {
+        Some(pid) if sysroot::system_root().is_none() => format!("/proc/{pid}").into_bytes(),
+        Some(_) => return None,
+        None if root.starts_with(b"/") => root.to_vec(),
+        None => return None,
+    };
+    sysroot::host_path(&path)
+        .symlink_metadata()
+        .ok()
+        .map(|m| m.uid())
+}
+
+/// Looks up the name of `uid` in the content of `/etc/passwd`.
+fn user_name_in(passwd: &[u8], uid: u32) -> Option<String> {
+    passwd.split(|&c| c == b'\n').find_map(|line| {
+        let mut fields = line.split(|&c| c == b':');
+        let name = fields.next()?;
+        let line_uid = fields.nth(1)?;
+        if std::str::from_utf8(line_uid).ok()?.parse() == Ok(uid) {
+            Some(String::from_utf8_lossy(name).into_owned())
+        } else {
+            None
+        }
+    })
+}
+
+/// The name of the user `uid` in the analysed system, or `uid N` if unknown.
+pub fn user_name(uid: u32) -> String {
+    let passwd = if sysroot::is_available() {
+        std::fs::read(sysroot::host_path(b"/etc/passwd")).ok()
+    } else {
+        None
+    };
+    passwd
+        .and_then(|passwd| user_name_in(&passwd, uid))
+        .unwrap_or_else(|| format!("uid {uid}"))
+}
+
+/// Finds all the gc roots of the store whose state directory (usually `/nix/var/nix`)
+/// is `state_dir`: indirect roots in `gcroots` and `profiles`, and temporary roots.
+///
+/// With `--system-root`, `state_dir` is a path of the analysed system.
+///
+/// If `local` is true, also reads temporary roots and scans running processes for roots.
+/// This only makes sense if the store is the one of the current machine: otherwise the
+/// processes which registered temporary roots are not running here.
+pub fn find_roots(
+    state_dir: &std::path::Path,
+    store_dir: &[u8],
+    local: bool,
+) -> io::Result<Vec<Root>> {
+    let system_root = sysroot::system_root();
+    let mut roots = Vec::new();
+    find_roots_in(
+        system_root,
+        &state_dir.join("gcroots"),
+        store_dir,
+        &mut roots,
+    )?;
+    find_roots_in(
+        system_root,
+        &state_dir.join("profiles"),
+        store_dir,
+        &mut roots,
+    )?;
+    if local {
+        find_temp_roots(state_dir, &mut roots)?;
+        find_runtime_roots(store_dir, &mut roots);
+    }
+    // the same root can be found several times, for example profiles are both direct roots
+    // and the target of an indirect root.
+    roots.sort();
+    roots.dedup();
+    Ok(roots)
+}
+
+/// Indirect roots in `state_dir/gcroots/auto` whose symlink is gone, for example because
+/// its directory was deleted: the path of the entry and its target. They keep nothing
+/// alive, and nix removes them during the next garbage collection.
+pub fn find_dangling_roots(state_dir: &std::path::Path) -> io::Result<Vec<Root>> {
+    let auto = state_dir.join("gcroots/auto");
+    let entries = match sysroot::host_path(auto.as_os_str().as_bytes()).read_dir() {
+        Ok(entries) => entries,
+        Err(ref e) if is_permanent(e) => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut res = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let target = match std::fs::read_link(entry.path()) {
+            Ok(t) => t,
+            Err(ref e) if is_permanent(e) || e.kind() == io::ErrorKind::InvalidInput => continue,
+            Err(e) => return Err(e),
+        };
+        let target = auto.join(target);
+        if sysroot::host_path(target.as_os_str().as_bytes())
+            .symlink_metadata()
+            .is_err()
+        {
+            let link = auto.join(entry.file_name());
+            res.push((
+                link.as_os_str().as_bytes().to_vec(),
+                target.as_os_str().as_bytes().to_vec(),
+            ));
+        }
+    }
+    res.sort();
+    Ok(res)
+}
+
+/// Returns the store directory, given a path in it.
+pub fn store_dir_of(path: &[u8]) -> Option<&[u8]> {
+    let path = std::path::Path::new(OsStr::from_bytes(path));
+    path.parent().map(|p| p.as_os_str().as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn store_path() {
+        assert_eq!(
+            to_store_path(b"/nix/store/aaaa-foo/bin/foo", b"/nix/store"),
+            Some(b"/nix/store/aaaa-foo".to_vec())
+        );
+        assert_eq!(
+            to_store_path(b"/nix/store/aaaa-foo", b"/nix/store"),
+            Some(b"/nix/store/aaaa-foo".to_vec())
+        );
+        assert_eq!(to_store_path(b"/nix/store", b"/nix/store"), None);
+        assert_eq!(to_store_path(b"/nix/storefoo/a", b"/nix/store"), None);
+        assert_eq!(to_store_path(b"/home/foo", b"/nix/store"), None);
+    }
+
+    #[test]
+    fn print_roots() {
+        let roots: &[u8] = b"/home/u/result -> /nix/store/aaaa-foo\n\n/nix/store/bbbb-bar\n";
+        assert_eq!(
+            parse_print_roots(roots).unwrap(),
+            vec![
+                (b"/home/u/result".to_vec(), b"/nix/store/aaaa-foo".to_vec()),
+                (
+                    b"/nix/store/bbbb-bar".to_vec(),
+                    b"/nix/store/bbbb-bar".to_vec()
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn processes() {
+        assert_eq!(pid_of(b"/proc/1234/maps"), Some(1234));
+        assert_eq!(pid_of(b"/proc/1234"), Some(1234));
+        assert_eq!(pid_of(b"/proc/self/exe"), None);
+        assert_eq!(pid_of(b"{censored}"), None);
+        assert_eq!(pid_of(b"{temp:12}"), Some(12));
+        assert_eq!(pid_of(b"{temp:12"), None);
+        let pid = std::process::id();
+        let label = String::from_utf8(process_label(pid)).unwrap();
+        if std::path::Path::new("/proc/self").exists() {
+            assert!(label.ends_with(&format!(" (pid {pid})")), "{}", label);
+        }
+        assert_eq!(
+            process_label(u32::MAX),
+            format!("pid {}", u32::MAX).into_bytes()
+        );
+    }
+
+    #[test]
+    fn owners() {
+        let passwd = b"root:x:0:0:System administrator:/root:/bin/sh\nalice:x:1000:100::/home/alice:/bin/sh\n";
+        assert_eq!(user_name_in(passwd, 1000), Some("alice".to_string()));
+        assert_eq!(user_name_in(passwd, 0), Some("root".to_string()));
+        assert_eq!(user_name_in(passwd, 1001), None);
+        assert_eq!(user_name(u32::MAX - 1), format!("uid {}", u32::MAX - 1));
+        assert_eq!(owner_of(b"{censored}"), None);
+        assert_eq!(owner_of(b"/nonexistent/nix-du/result"), None);
+        let uid = std::fs::symlink_metadata(std::env::temp_dir())
+            .unwrap()
+            .uid();
+        assert_eq!(
+            owner_of(std::env::temp_dir().as_os_str().as_bytes()),
+            Some(uid)
+        );
+    }
+
+    #[test]
+    fn walk_gcroots() {
+        let tmp = TempDir::new("gcroots");
+        let state = tmp.join("var");
+        let home = tmp.join("home");
+        std::fs::create_dir_all(state.join("gcroots/auto")).unwrap();
+        std::fs::create_dir_all(state.join("profiles")).unwrap();
+        std::fs::create_dir_all(&home).unwrap();
+        // direct root
+        symlink("/nix/store/aaaa-foo/bin", state.join("gcroots/direct")).unwrap();
+        // indirect root
+        symlink("/nix/store/bbbb-bar", home.join("result")).unwrap();
+        symlink(home.join("result"), state.join("gcroots/auto/xyz")).unwrap();
+        // stale indirect root
+        symlink(home.join("gone"), state.join("gcroots/auto/stale")).unwrap();
+        // profile
+        symlink("/nix/store/cccc-profile", state.join("profiles/p-1-link")).unwrap();
+        symlink("p-1-link", state.join("profiles/p")).unwrap();
+        // temporary root of a process which happens to have a live pid on this machine
+        std::fs::create_dir_all(state.join("temproots")).unwrap();
+        std::fs::write(
+            state.join(format!("temproots/{}", std::process::id())),
+            "/nix/store/dddd-temp\0",
+        )
+        .unwrap();
+
+        let mut roots = find_roots(&state, b"/nix/store", false).unwrap();
+        roots.sort();
+        let dangling = find_dangling_roots(&state).unwrap();
+        let expected = vec![
+            (home.join("result"), "/nix/store/bbbb-bar"),
+            (state.join("gcroots/direct"), "/nix/store/aaaa-foo"),
+            (state.join("profiles/p-1-link"), "/nix/store/cccc-profile"),
+        ];
+        let mut expected: Vec<Root> = expected
+            .into_iter()
+            .map(|(l, t)| (l.as_os_str().as_bytes().to_vec(), t.as_bytes().to_vec()))
+            .collect();
+        expected.sort();
+        assert_eq!(roots, expected);
+        assert_eq!(
+            dangling,
+            vec![(
+                state
+                    .join("gcroots/auto/stale")
+                    .as_os_str()
+                    .as_bytes()
+                    .to_vec(),
+                home.join("gone").as_os_str().as_bytes().to_vec()
+            )]
+        );
+    }
+
+    #[test]
+    fn walk_gcroots_under_system_root() {
+        let tmp = TempDir::new("gcroots-root");
+        std::fs::create_dir_all(tmp.join("nix/var/nix/gcroots/auto")).unwrap();
+        std::fs::create_dir_all(tmp.join("usr/home/u")).unwrap();
+        // an absolute symlink of the analysed system, not of this machine
+        symlink("/usr/home", tmp.join("home")).unwrap();
+        symlink("/nix/store/bbbb-bar", tmp.join("usr/home/u/result")).unwrap();
+        symlink("/home/u/result", tmp.join("nix/var/nix/gcroots/auto/xyz")).unwrap();
+        symlink(
+            "/nix/store/aaaa-foo",
+            tmp.join("nix/var/nix/gcroots/direct"),
+        )
+        .unwrap();
+
+        let mut roots = Vec::new();
+        find_roots_in(
+            Some(&*tmp),
+            std::path::Path::new("/nix/var/nix/gcroots"),
+            b"/nix/store",
+            &mut roots,
+        )
+        .unwrap();
+        roots.sort();
+        assert_eq!(
+            roots,
+            vec![
+                (b"/home/u/result".to_vec(), b"/nix/store/bbbb-bar".to_vec()),
+                (
+                    b"/nix/var/nix/gcroots/direct".to_vec(),
+                    b"/nix/store/aaaa-foo".to_vec()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,99 @@
+// SPDX-License-Identifier: LGPL-3.0
+
+//! Renders the overlap of gc roots as a heatmap in svg: one row and one column per root,
+//! cells coloured by how similar the closures of the two roots are.
+
+use crate::depgraph::DepInfos;
+use crate::report::Overlap;
+use bytesize::ByteSize;
+use scarlet::colormap::ColorMap;
+use scarlet::material_colors::MaterialPrimary;
+use scarlet::{colormap::ListedColorMap, prelude::*};
+use std::io::{self, Write};
+
+const CELL: usize = 32;
+/// approximate width of a character of the labels
+const CHAR: usize = 7;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn render<W: Write>(di: &DepInfos, overlap: &Overlap, w: &mut W) -> io::Result<()> {
+    let names: Vec<String> = overlap
+        .roots
+        .iter()
+        .enumerate()
+        .map(|(i, &idx)| {
+            format!(
+                "{}. {}",
+                i + 1,
+                escape(&String::from_utf8_lossy(&di.graph[idx].name()))
+            )
+        })
+        .collect();
+    let n = names.len();
+    let left = names.iter().map(|s| s.chars().count()).max().unwrap_or(0) * CHAR + CHAR;
+    let top = CELL;
+    let gradient = ListedColorMap::turbo();
+    let textcolors: Vec<RGBColor> = [MaterialPrimary::White, MaterialPrimary::Black]
+        .iter()
+        .map(|&c| RGBColor::from_material_palette(c))
+        .collect();
+
+    writeln!(
+        w,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="sans-serif" font-size="12">"#,
+        left + n * CELL,
+        top + n * CELL
+    )?;
+    for (i, name) in names.iter().enumerate() {
+        let middle = i * CELL + CELL / 2;
+        writeln!(
+            w,
+            r#"<text x="{}" y="{}" text-anchor="end" dominant-baseline="middle">{}</text>"#,
+            left - CHAR / 2,
+            top + middle,
+            name
+        )?;
+        writeln!(
+            w,
+            r#"<text x="{}" y="{}" text-anchor="middle" dominant-baseline="middle">{}</text>"#,
+            left + middle,
+            top / 2,
+            i + 1
+        )?;
+    }
+    for i in 0..n {
+        for j in 0..n {
+            let similarity = overlap.similarity(i, j);
+            let color: RGBColor = gradient.transform_single(similarity);
+            let textcolor = textcolors
+                .iter()
+                .max_by_key(|c| (c.distance(&color) * 1000.) as u64)
+                .expect("no possible textcolor");
+            let (x, y) = (left + j * CELL, top + i * CELL);
+            writeln!(
+                w,
+                r#"<g><title>{} and {} share {}</title><rect x="{}" y="{}" width="{}" height="{}" fill="{}"/><text x="{}" y="{}" text-anchor="middle" dominant-baseline="middle" font-size="10" fill="{}">{:.0}%</text></g>"#,
+                names[i],
+                names[j],
+                ByteSize::b(overlap.shared[i][j]),
+                x,
+                y,
+                CELL,
+                CELL,
+                color,
+                x + CELL / 2,
+                y + CELL / 2,
+                textcolor,
+                similarity * 100.
+            )?;
+        }
+    }
+    writeln!(w, "</svg>")?;
+    Ok(())
+}
@@ -5,16 +5,32 @@ use enum_map::enum_map;
 
 #[macro_use]
 pub mod msg;
+pub mod backend;
+pub mod binarycache;
 pub mod bindings;
 pub mod depgraph;
 pub mod dot;
+pub mod gcroots;
+pub mod heatmap;
 pub mod opt;
+pub mod pathinfo;
+pub mod profile;
 pub mod reduction;
+pub mod report;
+pub mod rules;
+pub mod snapshot;
+pub mod sqlite;
+pub mod sysroot;
+#[cfg(test)]
+mod testutil;
+pub mod why;
+use crate::backend::StoreBackend;
 use crate::msg::*;
 use bytesize::ByteSize;
-use std::ffi::OsString;
 use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 
 /* so that these functions are available in libnix_adepter.a */
 pub use crate::depgraph::{register_edge, register_node};
@@ -40,6 +56,15 @@ fn print_stats<W: io::Write>(w: &mut W, g: &depgraph::DepInfos) -> io::Result<()
     write!(w, "Size statistics for the ")?;
     let root = &g.graph[g.root];
     match root.description.path() {
+        None if g.metadata.reachable == Connected => {
+            write!(w, "closure of ")?;
+            for (i, idx) in g.roots().enumerate() {
+                if i > 0 {
+                    write!(w, ", ")?;
+                }
+                w.write_all(&g.graph[idx].name())?
+            }
+        }
         None => write!(w, "whole store")?,
         Some(p) => {
             write!(w, "closure of ")?;
@@ -64,6 +89,23 @@ fn print_stats<W: io::Write>(w: &mut W, g: &depgraph::DepInfos) -> io::Result<()
     Ok(())
 }
 
+/// Reads the dependency graph from `backend`, or exits with an error mentioning `source`,
+/// and the exit code of libnixstore if it failed.
+fn read_or_die<B: StoreBackend>(backend: B, source: &dyn std::fmt::Display) -> depgraph::DepInfos {
+    backend::read(backend).unwrap_or_else(|err| {
+        let code = err
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<depgraph::LibNixStoreError>())
+            .map_or(1, |e| e.0);
+        die!(
+            code,
+            "Could not read dependency graph from «{}»: {}",
+            source,
+            err
+        )
+    })
+}
+
 const LONG_ABOUT: &'static str = "
 This program outputs a graph on stdout in the dot format which may help you figuring out which \
 gc-roots should be removed in order to reclaim space in the nix store.
@@ -93,6 +135,7 @@ or with a user wide profile:
 /// Visualise what gc-roots you should delete to free space in your nix-store
 #[derive(Parser, Debug)]
 #[clap(version, about, long_about = LONG_ABOUT)]
+#[clap(group(clap::ArgGroup::new("roots_source").args(["path_info", "db", "binary_cache"])))]
 struct Args {
     /// Hide nodes below this size (a unit should be specified: -s=50MB)
     #[clap(short = 's', long, value_name = "SIZE")]
@@ -102,14 +145,86 @@ struct Args {
     #[clap(short = 'n', long, value_name = "N", conflicts_with = "min_size")]
     nodes: Option<u32>,
 
-    /// Consider the dependencies of PATH instead of all gc roots
+    /// Hide nodes containing store paths registered less than AGE ago, like 90d (units: h, d, w, m, y)
+    #[clap(long, value_name = "AGE", value_parser = depgraph::parse_age)]
+    registered_before: Option<Duration>,
+
+    /// Color nodes by size, or by age: the registration time of the newest store path they contain
+    #[clap(long, value_name = "WHAT", value_parser = ["size", "age"], default_value = "size")]
+    color_by: String,
+
+    /// Show one root per running process holding store paths in memory, instead of gathering them into {transient}
+    #[clap(long)]
+    per_process: bool,
+
+    /// Show one root per group of gc roots: by owner, by category (system, user profile, memory...), or for an absolute DIR, one per subdirectory of DIR containing roots
+    #[clap(long, value_name = "owner|category|DIR")]
+    group_roots: Option<reduction::RootGrouping>,
+
+    /// Print a report instead of a graph. packages: live store paths grouped by package name, with their versions, total size and the size they alone keep alive. profile: for each profile generation among roots (see --root), the space each installed element takes. orphans: gc roots in deleted directories, temporary directories or unused git checkouts (see --stale-after), and what they keep alive. roots: one line per gc root with what it alone keeps alive, its closure and what it shares with other roots (see --sort). overlap: csv matrix of the bytes kept alive by both roots of each pair of the N roots with the largest closure (-n, default 20). heatmap: the same as an svg heatmap
+    #[clap(long, value_name = "WHAT", value_parser = ["packages", "profile", "orphans", "roots", "overlap", "heatmap"])]
+    report: Option<String>,
+
+    /// Instead of a graph, print exactly what garbage collection would free if the gc roots ROOT were deleted, and the largest store paths which would go (show the N largest with -n)
+    #[clap(long, value_name = "ROOT", num_args = 1.., conflicts_with = "report")]
+    simulate_delete: Vec<PathBuf>,
+
+    /// Instead of a graph, print which gc roots to delete to free at least SIZE, for example 20GB. The current system and the current generation of profiles are never suggested
+    #[clap(long, value_name = "SIZE", conflicts_with_all = ["report", "simulate_delete", "group_roots"])]
+    free: Option<ByteSize>,
+
+    /// With --report roots, sort roots by this column: largest first, oldest first or by name
+    #[clap(long, value_name = "COLUMN", value_parser = ["exclusive", "closure", "shared", "age", "name"], default_value = "exclusive")]
+    sort: String,
+
+    /// With --report orphans, git checkouts unused for AGE are considered forgotten (units: h, d, w, m, y)
+    #[clap(long, value_name = "AGE", value_parser = depgraph::parse_age, default_value = "90d")]
+    stale_after: Duration,
+
+    /// Consider the dependencies of PATH instead of all gc roots (can be repeated)
     #[clap(short = 'r', long, value_name = "PATH")]
-    root: Option<PathBuf>,
+    root: Vec<PathBuf>,
 
-    /// Dump the unaltered graph read from store to the file passed as argument. Intended for debugging.
+    /// Open the store at URI, for example `local?root=/mnt`, `daemon` or `file:///var/cache` (default: as configured for nix)
+    #[clap(long, value_name = "URI", conflicts_with_all = ["path_info", "load", "db"])]
+    store: Option<String>,
+
+    /// Read the dependency graph from FILE, the output of `nix path-info --json --recursive`, instead of the store
+    #[clap(long, value_name = "FILE", conflicts_with_all = ["root", "load", "db"])]
+    path_info: Option<PathBuf>,
+
+    /// Read the dependency graph directly from the sqlite database of the store (usually /nix/var/nix/db/db.sqlite)
+    #[clap(long, value_name = "FILE", conflicts_with = "load")]
+    db: Option<PathBuf>,
+
+    /// Read the dependency graph from the .narinfo files of the binary cache in DIR (a file:// url is also accepted)
+    #[clap(long, value_name = "DIR", conflicts_with_all = ["root", "store", "load", "db", "path_info", "system_root"])]
+    binary_cache: Option<String>,
+
+    /// With --binary-cache, count the size of the unpacked nar (nar) or the size of the compressed file in the cache (file)
+    #[clap(long, value_name = "SIZE", value_parser = ["nar", "file"], default_value = "nar", requires = "binary_cache")]
+    cache_size: String,
+
+    /// With --path-info, --db or --binary-cache, read gc roots from FILE, the output of `nix-store --gc --print-roots`
+    #[clap(long, value_name = "FILE", requires = "roots_source")]
+    gc_roots: Option<PathBuf>,
+
+    /// Analyse the system whose root filesystem is mounted at DIR, for example /mnt; implies --db DIR/nix/var/nix/db/db.sqlite
+    #[clap(long, value_name = "DIR", conflicts_with_all = ["store", "path_info"])]
+    system_root: Option<PathBuf>,
+
+    /// Name and classify gc roots according to the rules in FILE, a json list of {"regex": ..., "label": ..., "category": ...}
+    #[clap(long, value_name = "FILE")]
+    rules: Option<PathBuf>,
+
+    /// Dump the unaltered graph read from store to the file passed as argument, in a format suitable for --load
     #[clap(long, value_name = "FILE")]
     dump: Option<PathBuf>,
 
+    /// Read the dependency graph from FILE, written by --dump, instead of the store
+    #[clap(long, value_name = "FILE", conflicts_with = "root")]
+    load: Option<PathBuf>,
+
     /// whether to take store optimisation into account: 0: no, 1: live paths, 2: all paths (default autodetect)
     #[clap(short='O', long, value_name="N", value_parser = ["0", "1", "2", "auto"])]
     opt_level: Option<String>,
@@ -117,6 +232,40 @@ struct Args {
     /// Don't print informationnal messages on stderr
     #[clap(short = 'q', long)]
     quiet: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Print the gc roots keeping PATH alive, each with a shortest chain of references to PATH
+    Why {
+        /// A store path, a path inside the store or a symlink to it
+        path: PathBuf,
+    },
+}
+
+/// Gathers roots as requested on the command line: builds, processes, groups and
+/// transient roots.
+fn merge_roots(mut g: depgraph::DepInfos, args: &Args) -> depgraph::DepInfos {
+    // pids are only meaningful if roots were found on this machine
+    let local_roots = sysroot::is_available() && sysroot::system_root().is_none();
+    let label = |pid| {
+        if local_roots {
+            gcroots::process_label(pid)
+        } else {
+            format!("pid {pid}").into_bytes()
+        }
+    };
+    if let Some(grouping) = &args.group_roots {
+        g = reduction::group_roots_by(g, grouping);
+    }
+    g = reduction::merge_build_roots(g, label);
+    if args.per_process {
+        g = reduction::merge_process_roots(g, label);
+    }
+    reduction::merge_transient_roots(g)
 }
 
 fn main() {
@@ -129,17 +278,47 @@ fn main() {
         Some("auto") | None => None,
         _ => unreachable!(),
     };
-    let root: Option<OsString> = args.root.as_ref().map(|path| {
-        let path_buf = PathBuf::from(path).canonicalize().unwrap_or_else(|err| {
+    if let Some(dir) = &args.system_root {
+        let dir = dir.canonicalize().unwrap_or_else(|err| {
             die!(
                 1,
                 "Could not canonicalize path «{}»: {}",
-                path.display(),
+                dir.display(),
                 err
             )
         });
-        OsString::from(path_buf)
-    });
+        sysroot::set_system_root(dir);
+    }
+    if let Some(path) = &args.rules {
+        let rules = std::fs::File::open(path)
+            .map(io::BufReader::new)
+            .and_then(rules::parse)
+            .unwrap_or_else(|err| {
+                die!(1, "Could not read rules from «{}»: {}", path.display(), err)
+            });
+        rules::set_rules(rules);
+    }
+    // pairs of the name of the root, as given on the command line, and its canonical path
+    let requested_roots: Vec<gcroots::Root> = args
+        .root
+        .iter()
+        .map(|path| {
+            let fail = |err| {
+                die!(
+                    1,
+                    "Could not canonicalize path «{}»: {}",
+                    path.display(),
+                    err
+                )
+            };
+            let absolute = std::path::absolute(path).unwrap_or_else(fail);
+            let canonical = sysroot::canonicalize(&absolute).unwrap_or_else(fail);
+            (
+                absolute.into_os_string().into_vec(),
+                canonical.into_os_string().into_vec(),
+            )
+        })
+        .collect();
     let dumpfile: Option<(std::fs::File, &PathBuf)> = args.dump.as_ref().map(|path| {
         let f = std::fs::File::create(path).unwrap_or_else(|err| {
             die!(1, "Could not open dump file «{}»: {}", path.display(), err)
@@ -153,9 +332,131 @@ fn main() {
      * end argument parsing               *
      **************************************/
 
-    msg!("Reading dependency graph from store... ");
-    let mut g = depgraph::DepInfos::read_from_store(root)
-        .unwrap_or_else(|res| die!(res, "Could not read from store"));
+    let open = |path: &PathBuf| {
+        std::fs::File::open(path)
+            .unwrap_or_else(|err| die!(1, "Could not open «{}»: {}", path.display(), err))
+    };
+    let db = match (&args.db, sysroot::system_root()) {
+        (None, Some(_)) if args.load.is_none() => {
+            Some(sysroot::host_path(b"/nix/var/nix/db/db.sqlite"))
+        }
+        (db, _) => db.clone(),
+    };
+    // only a database in the state directory of this machine has its gc roots here
+    let local_state_dir = std::env::var_os("NIX_STATE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/nix/var/nix"));
+    let is_local = |db: &PathBuf| match (sqlite::state_dir_of(db), local_state_dir.canonicalize()) {
+        (Some(dir), Ok(local)) => dir.canonicalize().ok() == Some(local),
+        _ => false,
+    };
+    let available = match &db {
+        _ if args.path_info.is_some()
+            || args.load.is_some()
+            || args.binary_cache.is_some()
+            || args.gc_roots.is_some() =>
+        {
+            false
+        }
+        Some(_) if sysroot::system_root().is_some() => true,
+        Some(db) => is_local(db),
+        None => true,
+    };
+    // the state directory of the analysed store, as a path of the analysed system
+    let state_dir = match &db {
+        None => Some(local_state_dir.clone()),
+        Some(db) => match sysroot::system_root() {
+            None => sqlite::state_dir_of(db),
+            Some(_) => Some(
+                sqlite::state_dir_of(db)
+                    .as_deref()
+                    .and_then(sysroot::system_path)
+                    .unwrap_or_else(|| PathBuf::from("/nix/var/nix")),
+            ),
+        },
+    };
+    if !available {
+        if let Some(reduction::RootGrouping::Owner) = args.group_roots {
+            die!(
+                1,
+                "--group-roots owner looks at gc roots on this machine, it cannot be used with roots found elsewhere"
+            );
+        }
+        sysroot::set_unavailable();
+    }
+    let mut g = match (&args.path_info, &args.load, &db) {
+        (None, None, None) if args.binary_cache.is_some() => {
+            let url = args.binary_cache.as_ref().unwrap();
+            let dir = PathBuf::from(url.strip_prefix("file://").unwrap_or(url));
+            let size = match args.cache_size.as_str() {
+                "nar" => binarycache::CacheSize::Nar,
+                "file" => binarycache::CacheSize::File,
+                _ => unreachable!(),
+            };
+            msg!("Reading dependency graph from {}... ", dir.display());
+            let roots = args.gc_roots.as_ref().map(|p| io::BufReader::new(open(p)));
+            read_or_die(
+                binarycache::BinaryCache {
+                    dir: dir.clone(),
+                    roots,
+                    size,
+                },
+                &dir.display(),
+            )
+        }
+        (Some(path), _, _) => {
+            msg!("Reading dependency graph from {}... ", path.display());
+            let json = io::BufReader::new(open(path));
+            let roots = args.gc_roots.as_ref().map(|p| io::BufReader::new(open(p)));
+            read_or_die(pathinfo::PathInfoDump { json, roots }, &path.display())
+        }
+        (None, Some(path), _) => {
+            msg!("Loading dependency graph from {}... ", path.display());
+            snapshot::load(io::BufReader::new(open(path))).unwrap_or_else(|err| {
+                die!(
+                    1,
+                    "Could not load dependency graph from «{}»: {}",
+                    path.display(),
+                    err
+                )
+            })
+        }
+        (None, None, Some(path)) => {
+            msg!("Reading dependency graph from {}... ", path.display());
+            let roots = args.gc_roots.as_ref().map(|p| {
+                gcroots::parse_print_roots(io::BufReader::new(open(p))).unwrap_or_else(|err| {
+                    die!(1, "Could not read gc roots from «{}»: {}", p.display(), err)
+                })
+            });
+            // only look for temporary roots and roots in running processes if this is the
+            // store of this machine
+            let local_roots = sysroot::system_root().is_none() && is_local(path);
+            let conf_dir = std::env::var_os("NIX_CONF_DIR")
+                .map(PathBuf::from)
+                .unwrap_or_else(|| sysroot::host_path(b"/etc/nix"));
+            read_or_die(
+                sqlite::Database {
+                    path,
+                    closure_of: requested_roots,
+                    roots,
+                    state_dir: state_dir.as_deref(),
+                    local_roots,
+                    settings: sqlite::GcSettings::from_conf_dir(&conf_dir),
+                },
+                &path.display(),
+            )
+        }
+        (None, None, None) => {
+            msg!("Reading dependency graph from store... ");
+            read_or_die(
+                depgraph::LibNixStore {
+                    roots: requested_roots,
+                    store: args.store.clone(),
+                },
+                &"store",
+            )
+        }
+    };
     msg!(
         "{} nodes, {} edges read.\n",
         g.graph.node_count(),
@@ -166,19 +467,57 @@ fn main() {
      * handling of --dump
      * **********************************/
 
-    if let Some((mut f, path)) = dumpfile {
+    if let Some((f, path)) = dumpfile {
         msg!("Dumping dependency graph to {}...", path.display());
-        dot::render(&g, &mut f)
+        let mut f = io::BufWriter::new(f);
+        snapshot::dump(&g, &mut f)
+            .and_then(|_| io::Write::flush(&mut f))
             .unwrap_or_else(|err| die!(1, "Could not dump dependency graph: {}", err));
         drop(f);
         msg!(" done\n");
     }
 
+    /*******************
+     * nix-du why      *
+     *******************/
+
+    if let Some(Command::Why { path }) = &args.command {
+        let absolute = std::path::absolute(path).unwrap_or_else(|_| path.clone());
+        let canonical = sysroot::canonicalize(&absolute).unwrap_or(absolute);
+        let canonical = canonical.as_os_str().as_bytes();
+        let target = g
+            .graph
+            .node_indices()
+            .find(|&idx| {
+                g.graph[idx].kind() == depgraph::NodeKind::Path
+                    && g.graph[idx].description.path().is_some_and(|p| {
+                        canonical.starts_with(p)
+                            && matches!(canonical.get(p.len()), None | Some(b'/'))
+                    })
+            })
+            .unwrap_or_else(|| die!(1, "«{}» is not a valid store path", path.display()));
+        let chains = why::chains(&g, target);
+        let stdout = io::stdout();
+        let mut handle = stdout.lock();
+        match why::print_chains(&g, target, &chains, &mut handle) {
+            Ok(_) => (),
+            Err(ref x) if x.kind() == io::ErrorKind::BrokenPipe => (),
+            Err(x) => die!(3, "While writing to stdout: {}", x),
+        }
+        return;
+    }
+
     /******************
      * handling or -O *
      ******************/
 
     let default_optlevel = Some(StatOpts::Alive);
+    // the paths of a path-info dump, a snapshot or a binary cache are usually not on this machine
+    let offline = args.path_info.is_some() || args.load.is_some() || args.binary_cache.is_some();
+    let optlevel = match optlevel {
+        None if offline => Some(None),
+        o => o,
+    };
     let optlevel = optlevel.unwrap_or_else(|| match opt::store_is_optimised(&g) {
         Err(e) => {
             eprintln!("Could not auto detect store optimisation: {}", e);
@@ -208,29 +547,160 @@ fn main() {
         print_stats(&mut handle, &g).expect("could not write to stderr");
     });
 
+    /*******************
+     * reports         *
+     *******************/
+
+    if !args.simulate_delete.is_empty() {
+        if g.metadata.reachable == depgraph::Reachability::Disconnected {
+            g = reduction::keep_reachable(g);
+        }
+        let roots: Vec<_> = args
+            .simulate_delete
+            .iter()
+            .flat_map(|root| {
+                let found = report::find_roots_to_delete(&g, root, sysroot::is_available());
+                if found.is_empty() {
+                    die!(1, "«{}» is not a gc root", root.display());
+                }
+                found
+            })
+            .collect();
+        let deletion = report::simulate_delete(&g, &roots);
+        let limit = args.nodes.map_or(20, |n| n as usize);
+        let stdout = io::stdout();
+        let mut handle = stdout.lock();
+        match report::print_deletion(&g, &deletion, limit, &mut handle) {
+            Ok(_) => (),
+            Err(ref x) if x.kind() == io::ErrorKind::BrokenPipe => (),
+            Err(x) => die!(3, "While writing to stdout: {}", x),
+        }
+        return;
+    }
+
+    if let Some(report) = &args.report {
+        if g.metadata.reachable == depgraph::Reachability::Disconnected {
+            g = reduction::keep_reachable(g);
+        }
+        match report.as_str() {
+            "roots" => g = merge_roots(g, &args),
+            "overlap" | "heatmap" => g = reduction::condense(merge_roots(g, &args)),
+            _ => (),
+        }
+        let stdout = io::stdout();
+        let mut handle = stdout.lock();
+        let res = match report.as_str() {
+            "packages" => report::print_packages(&report::packages(&g), &mut handle),
+            "profile" => match report::print_profiles(&g, &mut handle) {
+                Ok(0) => die!(
+                    1,
+                    "No profile generation among the roots, pass for example --root ~/.nix-profile"
+                ),
+                res => res.map(drop),
+            },
+            "roots" => {
+                let column = match args.sort.as_str() {
+                    "exclusive" => report::RootColumn::Exclusive,
+                    "closure" => report::RootColumn::Closure,
+                    "shared" => report::RootColumn::Shared,
+                    "age" => report::RootColumn::Age,
+                    "name" => report::RootColumn::Name,
+                    _ => unreachable!(),
+                };
+                let mut usages = report::root_usages(&g);
+                report::sort_roots(&g, &mut usages, column);
+                report::print_roots(&g, &usages, &mut handle)
+            }
+            "overlap" | "heatmap" => {
+                let overlap = report::overlap(&g, args.nodes.map_or(20, |n| n as usize));
+                if report == "overlap" {
+                    report::print_overlap_csv(&g, &overlap, &mut handle)
+                } else {
+                    heatmap::render(&g, &overlap, &mut handle)
+                }
+            }
+            "orphans" => {
+                if !sysroot::is_available() {
+                    die!(
+                        1,
+                        "--report orphans looks at gc roots on this machine, it cannot be used with roots found elsewhere"
+                    );
+                }
+                let (orphans, together) = report::orphans(&g, args.stale_after);
+                let dangling = state_dir
+                    .as_deref()
+                    .map_or(Ok(Vec::new()), gcroots::find_dangling_roots)
+                    .unwrap_or_else(|e| {
+                        msg!(
+                            "warning: could not look for dangling indirect roots: {}\n",
+                            e
+                        );
+                        Vec::new()
+                    });
+                report::print_orphans(&g, &orphans, together, &dangling, &mut handle)
+            }
+            _ => unreachable!(),
+        };
+        match res {
+            Ok(_) => (),
+            Err(ref x) if x.kind() == io::ErrorKind::BrokenPipe => (),
+            Err(x) => die!(3, "While writing to stdout: {}", x),
+        }
+        return;
+    }
+
     /*******************
      * graph reduction *
      *******************/
 
-    g = reduction::merge_transient_roots(g);
+    g = merge_roots(g, &args);
     msg!("Computing quotient graph... ");
     g = reduction::condense(g);
 
-    let mut min_size = args.min_size.map(|s| s.as_u64()).unwrap_or(0);
-    if let Some(n_nodes) = args.nodes {
-        if (n_nodes as usize) < g.graph.node_count() {
-            let mut sizes: Vec<u64> = g.graph.raw_nodes().iter().map(|n| n.weight.size).collect();
-            sizes.sort_unstable();
-            min_size = sizes[sizes.len().saturating_sub(n_nodes as usize)] as u64;
+    if let Some(target) = args.free {
+        if !sysroot::is_available() {
+            msg!("warning: gc roots were not found on this machine, the latest generation of each profile is assumed to be the current one\n");
+        }
+        let picks = report::recommend_deletions(&g, target.as_u64(), !sysroot::is_available());
+        let stdout = io::stdout();
+        let mut handle = stdout.lock();
+        match report::print_recommendation(&g, &picks, target.as_u64(), &mut handle) {
+            Ok(_) => (),
+            Err(ref x) if x.kind() == io::ErrorKind::BrokenPipe => (),
+            Err(x) => die!(3, "While writing to stdout: {}", x),
         }
+        return;
     }
 
+    let min_size = match args.nodes {
+        Some(n_nodes) => reduction::min_size_for_nodes(&g, n_nodes as usize),
+        None => args.min_size.map(|s| s.as_u64()).unwrap_or(0),
+    };
+
     /*******************
      * filter handling *
      *******************/
 
-    if min_size > 0 {
-        g = reduction::keep(g, |d: &depgraph::DepNode| d.size >= min_size);
+    let registered_before = args.registered_before.map(|age| {
+        let cutoff = SystemTime::now()
+            .checked_sub(age)
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok());
+        cutoff.map_or(0, |t| t.as_secs())
+    });
+    if registered_before.is_some()
+        && g.graph
+            .raw_nodes()
+            .iter()
+            .all(|n| n.weight.registration_time().is_none())
+    {
+        msg!("warning: registration times are unknown, --registered-before hides everything\n");
+    }
+    if min_size > 0 || registered_before.is_some() {
+        g = reduction::keep(g, |d: &depgraph::DepNode| {
+            d.size >= min_size
+                && registered_before
+                    .is_none_or(|before| d.registration_time().is_some_and(|t| t <= before))
+        });
     }
     msg!(
         "{} nodes, {} edges.\n",
@@ -247,7 +717,12 @@ fn main() {
     {
         let stdout = io::stdout();
         let mut handle = stdout.lock();
-        match dot::render(&g, &mut handle) {
+        let coloring = match args.color_by.as_str() {
+            "size" => dot::Coloring::Size,
+            "age" => dot::Coloring::Age,
+            _ => unreachable!(),
+        };
+        match dot::render(&g, coloring, &mut handle) {
             Ok(_) => (),
             Err(ref x) if x.kind() == io::ErrorKind::BrokenPipe => (),
             Err(x) => die!(3, "While writing to stdout: {}", x),
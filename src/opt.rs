@@ -1,5 +1,6 @@
 use crate::depgraph::*;
 use crate::msg::*;
+use crate::sysroot;
 
 use dashmap::mapref::entry::Entry;
 use petgraph::prelude::NodeIndex;
@@ -7,7 +8,6 @@ use rayon::prelude::*;
 use std::io::Result;
 use std::iter::once;
 use std::os::unix::fs::MetadataExt;
-use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::RwLock;
 use walkdir::{DirEntryExt, WalkDir};
@@ -62,10 +62,10 @@ pub fn refine_optimized_store(di: &mut DepInfos) -> Result<()> {
                 if weight.kind() != NodeKind::Path {
                     return Ok(());
                 }
-                let path = std::path::Path::new(
+                let path = sysroot::host_path(
                     weight
                         .description
-                        .path_as_os_str()
+                        .path()
                         .expect("node with kind path without path"),
                 );
 
@@ -111,6 +111,7 @@ pub fn refine_optimized_store(di: &mut DepInfos) -> Result<()> {
                                 let new_node = graph.add_node(DepNode {
                                     description: NodeDescription::Shared(name),
                                     size: filesize,
+                                    info: None,
                                 });
                                 graph.add_edge(n, new_node, ());
                                 let new_w = &mut graph[n];
@@ -152,7 +153,7 @@ pub fn store_is_optimised(di: &DepInfos) -> Result<Option<bool>> {
         Some(ref node) => &node.weight,
         None => return Ok(None),
     };
-    let mut p = PathBuf::from(drv.description.path_as_os_str().unwrap().to_os_string());
+    let mut p = sysroot::host_path(drv.description.path().unwrap());
     // compute the location of .links
     if !p.pop() {
         return Ok(None);
@@ -0,0 +1,203 @@
+// SPDX-License-Identifier: LGPL-3.0
+
+//! Reads the dependency graph from the output of `nix path-info --json --recursive`
+//! instead of querying libnixstore.
+
+use crate::backend::{invalid_data, MemoryStore, StoreBackend};
+use crate::depgraph::*;
+use crate::gcroots::parse_print_roots;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Read};
+
+/// One entry of the output of `nix path-info --json`.
+///
+/// Only the fields nix-du cares about are parsed.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PathInfo {
+    /// absent when the json is an object keyed by path (nix >= 2.19)
+    path: Option<String>,
+    /// `false` when the path is not valid, in which case all other fields are absent
+    valid: Option<bool>,
+    #[serde(default)]
+    nar_size: u64,
+    #[serde(default)]
+    references: Vec<String>,
+    deriver: Option<String>,
+    registration_time: Option<u64>,
+    #[serde(default)]
+    ultimate: bool,
+    #[serde(default)]
+    signatures: Vec<String>,
+    /// a string, or an object in recent versions of nix
+    #[serde(default)]
+    ca: serde_json::Value,
+}
+
+/// `nix path-info --json` outputs a list before nix 2.19, and an object keyed by store
+/// path after.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum PathInfoJson {
+    List(Vec<PathInfo>),
+    Map(BTreeMap<String, Option<PathInfo>>),
+}
+
+/// Recent versions of nix only print the base name of references.
+fn full_path(reference: &str, referrer: &str) -> Path {
+    if reference.starts_with('/') {
+        return reference.as_bytes().to_vec();
+    }
+    let store_dir = match referrer.rfind('/') {
+        Some(i) => &referrer[..=i],
+        None => "/nix/store/",
+    };
+    format!("{store_dir}{reference}").into_bytes()
+}
+
+/// The output of `nix path-info --json --recursive`, as a backend.
+///
+/// If `roots` is given, it is parsed as the output of `nix-store --gc --print-roots`
+/// and the result is the same as reading the whole store with `LibNixStore` without
+/// root. Otherwise, the paths which are not referenced by any other path are considered
+/// as roots and the graph is considered connected, like `LibNixStore` with a root.
+///
+/// Edges from outputs to their derivation are added when the derivation is part of
+/// the dump, as with the default value of `keep-derivations`.
+pub struct PathInfoDump<R, S> {
+    pub json: R,
+    pub roots: Option<S>,
+}
+
+impl<R: Read, S: BufRead> StoreBackend for PathInfoDump<R, S> {
+    fn contents(self) -> io::Result<MemoryStore> {
+        let dump: PathInfoJson = serde_json::from_reader(self.json)?;
+        let infos: Vec<(String, PathInfo)> = match dump {
+            PathInfoJson::List(list) => list
+                .into_iter()
+                .map(|info| match info.path.clone() {
+                    Some(path) => Ok((path, info)),
+                    None => Err(invalid_data("path-info entry without path".into())),
+                })
+                .collect::<io::Result<_>>()?,
+            PathInfoJson::Map(map) => map
+                .into_iter()
+                .filter_map(|(path, info)| info.map(|info| (path, info)))
+                .collect(),
+        };
+
+        let mut store = MemoryStore::new();
+        let infos: Vec<_> = infos
+            .into_iter()
+            .filter(|(_, info)| info.valid != Some(false))
+            .collect();
+        for (path, info) in &infos {
+            let idx = store.add_path(path.as_bytes().to_vec(), info.nar_size);
+            store.set_info(
+                idx,
+                StorePathInfo {
+                    deriver: info
+                        .deriver
+                        .as_ref()
+                        .map(|d| String::from_utf8_lossy(&full_path(d, path)).into_owned()),
+                    registration_time: info.registration_time,
+                    content_addressed: !info.ca.is_null(),
+                    signed: !info.signatures.is_empty(),
+                    ultimate: info.ultimate,
+                },
+            );
+        }
+        for (path, info) in &infos {
+            let from = store.index_of(path.as_bytes()).unwrap();
+            for reference in &info.references {
+                let to = match store.index_of(&full_path(reference, path)) {
+                    Some(idx) => idx,
+                    None => {
+                        return Err(invalid_data(format!(
+                            "{path} references {reference} which is not part of the dump, was it created with --recursive?"
+                        )))
+                    }
+                };
+                store.add_reference(from, to);
+            }
+            if let Some(deriver) = &info.deriver {
+                if let Some(drv) = store.index_of(&full_path(deriver, path)) {
+                    store.add_reference(from, drv);
+                }
+            }
+        }
+
+        if let Some(roots) = self.roots {
+            store.add_gc_roots(parse_print_roots(roots)?);
+        }
+        Ok(store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend;
+
+    fn read_path_info(json: &str, roots: Option<&[u8]>) -> io::Result<DepInfos> {
+        backend::read(PathInfoDump {
+            json: json.as_bytes(),
+            roots,
+        })
+    }
+
+    const OLD_FORMAT: &str = r#"[
+        {"path":"/nix/store/aaaa-foo","narHash":"sha256:x","narSize":100,"references":["/nix/store/bbbb-bar","/nix/store/aaaa-foo"],"deriver":"/nix/store/cccc-foo.drv","registrationTime":1,"valid":true},
+        {"path":"/nix/store/bbbb-bar","narSize":50,"references":[],"valid":true}
+    ]"#;
+
+    const NEW_FORMAT: &str = r#"{
+        "/nix/store/aaaa-foo":{"narHash":"sha256:x","narSize":100,"references":["bbbb-bar"],"deriver":"cccc-foo.drv"},
+        "/nix/store/bbbb-bar":{"narSize":50,"references":[]},
+        "/nix/store/dddd-invalid":null
+    }"#;
+
+    #[test]
+    fn without_roots() {
+        for json in &[OLD_FORMAT, NEW_FORMAT] {
+            let di = read_path_info(json, None).unwrap();
+            di.check_metadata();
+            assert_eq!(di.graph.node_count(), 3);
+            assert_eq!(di.graph.edge_count(), 2);
+            assert_eq!(di.reachable_size(), 150);
+            assert_eq!(di.metadata.reachable, Reachability::Connected);
+            assert_eq!(
+                di.roots_name(),
+                ["foo".to_string()].iter().cloned().collect()
+            );
+            let foo = di.roots().next().unwrap();
+            let info = di.graph[foo].info.as_ref().unwrap();
+            assert_eq!(info.deriver.as_deref(), Some("/nix/store/cccc-foo.drv"));
+        }
+    }
+
+    #[test]
+    fn with_roots() {
+        let roots: &[u8] =
+            b"/home/user/result -> /nix/store/bbbb-bar\n{censored} -> /nix/store/aaaa-foo\n/proc/12/exe -> /nix/store/eeee-gone\n";
+        let di = read_path_info(NEW_FORMAT, Some(roots)).unwrap();
+        di.check_metadata();
+        assert_eq!(di.metadata.reachable, Reachability::Disconnected);
+        assert_eq!(di.graph[di.root].kind(), NodeKind::Dummy);
+        let kinds: Vec<_> = di.roots().map(|idx| di.graph[idx].kind()).collect();
+        assert_eq!(kinds.len(), 2);
+        assert!(kinds.contains(&NodeKind::Link));
+        assert!(kinds.contains(&NodeKind::Memory));
+        assert_eq!(
+            di.metadata.size[DedupAwareness::Unaware][Reachability::Disconnected],
+            Some(150)
+        );
+    }
+
+    #[test]
+    fn missing_reference() {
+        let json = r#"{"/nix/store/aaaa-foo":{"narSize":100,"references":["bbbb-bar"]}}"#;
+        assert!(read_path_info(json, None).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+// SPDX-License-Identifier: LGPL-3.0
+
+//! Reads which elements are installed in a profile generation, from the `manifest.json`
+//! written by `nix profile` or the `manifest.nix` written by `nix-env`.
+
+use crate::backend::invalid_data;
+use crate::depgraph::{split_name, Path};
+use crate::sysroot;
+use std::io;
+
+/// An element installed in a profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+    pub name: String,
+    pub store_paths: Vec<Path>,
+}
+
+/// The name of the package in a store path, like `hello` for `/nix/store/hash-hello-2.12`
+fn pname_of(path: &[u8]) -> String {
+    let base = path.rsplit(|&c| c == b'/').next().unwrap_or(path);
+    let name = match base.iter().position(|&c| c == b'-') {
+        Some(i) => &base[i + 1..],
+        None => base,
+    };
+    String::from_utf8_lossy(split_name(name).0).into_owned()
+}
+
+/// Parses a `manifest.json`: elements are an object keyed by name since version 3, and a
+/// list before.
+fn parse_manifest_json(content: &[u8]) -> io::Result<Vec<Element>> {
+    let manifest: serde_json::Value = serde_json::from_slice(content)?;
+    let store_paths = |element: &serde_json::Value| -> Vec<Path> {
+        element["storePaths"]
+            .as_array()
+            .map(|paths| {
+                paths
+                    .iter()
+                    .filter_map(|p| p.as_str())
+                    .map(|p| p.as_bytes().to_vec())
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let res = match &manifest["elements"] {
+        serde_json::Value::Object(elements) => elements
+            .iter()
+            .map(|(name, element)| Element {
+                name: name.clone(),
+                store_paths: store_paths(element),
+            })
+            .collect(),
+        serde_json::Value::Array(elements) => elements
+            .iter()
+            .map(|element| {
+                let store_paths = store_paths(element);
+                let name = match element["attrPath"].as_str() {
+                    Some(attr) => attr.rsplit('.').next().unwrap_or(attr).to_string(),
+                    None => store_paths.first().map(|p| pname_of(p)).unwrap_or_default(),
+                };
+                Element { name, store_paths }
+            })
+            .collect(),
+        _ => return Err(invalid_data("manifest.json without elements".into())),
+    };
+    Ok(res)
+}
+
+/// Extracts elements from a `manifest.nix`: a list of attribute sets, of which only the
+/// `name` and the `outPath`s are used.
+fn parse_manifest_nix(content: &[u8]) -> Vec<Element> {
+    let mut res = Vec::new();
+    let mut current: Option<Element> = None;
+    let mut depth = 0usize;
+    // the identifier being read, and the key of the value being read
+    let mut ident = Vec::new();
+    let mut key: Option<Vec<u8>> = None;
+    let mut i = 0;
+    while i < content.len() {
+        let c = content[i];
+        match c {
+            b'"' => {
+                let mut value = Vec::new();
+                i += 1;
+                while i < content.len() && content[i] != b'"' {
+                    if content[i] == b'\\' {
+                        i += 1;
+                    }
+                    if let Some(&c) = content.get(i) {
+                        value.push(c);
+                    }
+                    i += 1;
+                }
+                if let (Some(element), Some(key)) = (&mut current, &key) {
+                    match key.as_slice() {
+                        b"name" if depth == 1 => {
+                            element.name = String::from_utf8_lossy(&value).into_owned()
+                        }
+                        b"outPath" if !element.store_paths.contains(&value) => {
+                            element.store_paths.push(value)
+                        }
+                        _ => (),
+                    }
+                }
+                key = None;
+                ident.clear();
+            }
+            b'{' => {
+                depth += 1;
+                if depth == 1 {
+                    current = Some(Element {
+                        name: String::new(),
+                        store_paths: Vec::new(),
+                    });
+                }
+                key = None;
+                ident.clear();
+            }
+            b'}' => {
+                if depth == 1 {
+                    res.extend(current.take());
+                }
+                depth = depth.saturating_sub(1);
+                key = None;
+                ident.clear();
+            }
+            b'=' => key = Some(std::mem::take(&mut ident)),
+            c if c.is_ascii_alphanumeric() || c == b'_' || c == b'-' => ident.push(c),
+            c if c.is_ascii_whitespace() => (),
+            _ => {
+                key = None;
+                ident.clear();
+            }
+        }
+        i += 1;
+    }
+    res
+}
+
+/// Reads the elements installed in the profile generation whose store path is
+/// `generation`. Returns `None` if it has no manifest, i.e. it is not a profile.
+pub fn read_manifest(generation: &[u8]) -> io::Result<Option<Vec<Element>>> {
+    let dir = sysroot::host_path(generation);
+    match std::fs::read(dir.join("manifest.json")) {
+        Ok(content) => return parse_manifest_json(&content).map(Some),
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        Err(_) => (),
+    }
+    match std::fs::read(dir.join("manifest.nix")) {
+        Ok(content) => Ok(Some(parse_manifest_nix(&content))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(name: &str, paths: &[&str]) -> Element {
+        Element {
+            name: name.to_string(),
+            store_paths: paths.iter().map(|p| p.as_bytes().to_vec()).collect(),
+        }
+    }
+
+    #[test]
+    fn manifest_json() {
+        let v3 = r#"{"version":3,"elements":{
+            "hello":{"active":true,"priority":5,"storePaths":["/nix/store/aaaa-hello-2.12"]},
+            "python3":{"active":true,"storePaths":["/nix/store/bbbb-python3-3.11.6","/nix/store/cccc-python3-3.11.6-dev"]}
+        }}"#;
+        assert_eq!(
+            parse_manifest_json(v3.as_bytes()).unwrap(),
+            vec![
+                element("hello", &["/nix/store/aaaa-hello-2.12"]),
+                element(
+                    "python3",
+                    &[
+                        "/nix/store/bbbb-python3-3.11.6",
+                        "/nix/store/cccc-python3-3.11.6-dev"
+                    ]
+                ),
+            ]
+        );
+        let v2 = r#"{"version":2,"elements":[
+            {"active":true,"attrPath":"legacyPackages.x86_64-linux.hello","storePaths":["/nix/store/aaaa-hello-2.12"]},
+            {"active":true,"storePaths":["/nix/store/dddd-ripgrep-14.1.0"]}
+        ]}"#;
+        assert_eq!(
+            parse_manifest_json(v2.as_bytes()).unwrap(),
+            vec![
+                element("hello", &["/nix/store/aaaa-hello-2.12"]),
+                element("ripgrep", &["/nix/store/dddd-ripgrep-14.1.0"]),
+            ]
+        );
+        assert!(parse_manifest_json(b"{}").is_err());
+    }
+
+    #[test]
+    fn manifest_nix() {
+        let manifest = r#"[ { meta = { description = "A {curly} \"quoted\" program"; name = "not-this"; }; name = "hello-2.12"; out = { outPath = "/nix/store/aaaa-hello-2.12"; }; outPath = "/nix/store/aaaa-hello-2.12"; outputs = [ "out" ]; system = "x86_64-linux"; type = "derivation"; } { name = "git-2.44.0"; out = { outPath = "/nix/store/eeee-git-2.44.0"; }; doc = { outPath = "/nix/store/ffff-git-2.44.0-doc"; }; outPath = "/nix/store/eeee-git-2.44.0"; } ]"#;
+        assert_eq!(
+            parse_manifest_nix(manifest.as_bytes()),
+            vec![
+                element("hello-2.12", &["/nix/store/aaaa-hello-2.12"]),
+                element(
+                    "git-2.44.0",
+                    &[
+                        "/nix/store/eeee-git-2.44.0",
+                        "/nix/store/ffff-git-2.44.0-doc"
+                    ]
+                ),
+            ]
+        );
+    }
+}
@@ -4,9 +4,12 @@ use std::collections;
 use std::hash::Hasher;
 use std::{self, hash::Hash};
 
+use petgraph::prelude::NodeIndex;
 use petgraph::visit::{DfsPostOrder, EdgeFiltered, EdgeRef, IntoEdgeReferences};
 
 use crate::depgraph::*;
+use crate::gcroots;
+use std::os::unix::ffi::OsStrExt;
 
 /// Merges all the in memory roots in one root
 /// noop is no in memory root is present
@@ -28,6 +31,7 @@ pub fn merge_transient_roots(mut di: DepInfos) -> DepInfos {
     let fake_root_idx = di.graph.add_node(DepNode {
         description: NodeDescription::Transient,
         size: 0,
+        info: None,
     });
     di.graph.add_edge(di.root, fake_root_idx, ());
     for idx in targets {
@@ -38,6 +42,164 @@ pub fn merge_transient_roots(mut di: DepInfos) -> DepInfos {
     di
 }
 
+/// Moves each root for which `group` returns `Some(key)` under a new root per key,
+/// described by `describe(key, roots of the group, graph)`.
+/// noop if the graph is rooted in a fs node
+fn group_roots<K, G, D>(mut di: DepInfos, group: G, mut describe: D) -> DepInfos
+where
+    K: Ord,
+    G: Fn(&NodeDescription) -> Option<K>,
+    D: FnMut(&K, &[NodeIndex], &DepGraph) -> NodeDescription,
+{
+    if di.graph[di.root].kind() != NodeKind::Dummy {
+        // this graph is rooted in a fs node, no runtime roots
+        return di;
+    }
+
+    let mut groups = collections::BTreeMap::<K, Vec<NodeIndex>>::new();
+    for idx in di.roots() {
+        if let Some(key) = group(&di.graph[idx].description) {
+            groups.entry(key).or_default().push(idx);
+        }
+    }
+    for (key, members) in groups {
+        let description = describe(&key, &members, &di.graph);
+        let group_idx = di.graph.add_node(DepNode {
+            description,
+            size: 0,
+            info: None,
+        });
+        di.graph.add_edge(di.root, group_idx, ());
+        for idx in members {
+            let edx = di.graph.find_edge(di.root, idx).unwrap();
+            di.graph.remove_edge(edx);
+            di.graph.add_edge(group_idx, idx, ());
+        }
+    }
+    di
+}
+
+/// Gathers the in memory roots of each process in one root per process, labelled by
+/// `label(pid)`. In memory roots which cannot be attributed to a process are left alone.
+/// noop if no such root is present
+pub fn merge_process_roots<F: Fn(u32) -> Path>(di: DepInfos, label: F) -> DepInfos {
+    group_roots(
+        di,
+        |description| match description {
+            NodeDescription::Memory(path) => gcroots::pid_of(path),
+            _ => None,
+        },
+        |&pid, _, _| NodeDescription::Process(label(pid)),
+    )
+}
+
+/// Gathers the temporary roots of each nix process in one root per process, labelled by
+/// `label(pid)` followed by the derivation it is building, if it can be guessed.
+/// noop if no temporary root is present
+pub fn merge_build_roots<F: Fn(u32) -> Path>(di: DepInfos, label: F) -> DepInfos {
+    group_roots(
+        di,
+        |description| match description {
+            NodeDescription::Temporary(path) => gcroots::pid_of(path),
+            _ => None,
+        },
+        |&pid, roots, g| {
+            let mut res = label(pid);
+            // nix registers the derivations it builds and their inputs as temporary
+            // roots: what is being built is the derivations no other one depends on.
+            let drvs: collections::BTreeSet<_> = roots
+                .iter()
+                .flat_map(|&idx| g.neighbors(idx))
+                .filter(|&idx| {
+                    g[idx]
+                        .description
+                        .path()
+                        .is_some_and(|p| p.ends_with(b".drv"))
+                })
+                .collect();
+            let mut building = drvs.iter().filter(|&&idx| {
+                !g.neighbors_directed(idx, petgraph::Direction::Incoming)
+                    .any(|referrer| referrer != idx && drvs.contains(&referrer))
+            });
+            if let Some(&first) = building.next() {
+                res.extend_from_slice(b", building ");
+                res.extend_from_slice(&g[first].name());
+                match building.count() {
+                    0 => (),
+                    n => res.extend_from_slice(format!(" and {n} more").as_bytes()),
+                }
+            }
+            NodeDescription::Build(res)
+        },
+    )
+}
+
+/// A way to gather gc roots with `group_roots_by`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RootGrouping {
+    /// by the user owning the root link or the process holding the root
+    Owner,
+    /// by category, see `NodeDescription::category`
+    Category,
+    /// the roots under this directory by the subdirectory they are in, for example each
+    /// project under `~/src`
+    Directory(std::path::PathBuf),
+}
+
+impl std::str::FromStr for RootGrouping {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "owner" => Ok(RootGrouping::Owner),
+            "category" => Ok(RootGrouping::Category),
+            dir if dir.starts_with('/') => Ok(RootGrouping::Directory(dir.into())),
+            _ => Err(format!(
+                "expected owner, category or an absolute directory, got {s:?}"
+            )),
+        }
+    }
+}
+
+/// The subdirectory of `dir` containing `link`, or `dir` itself if `link` is directly
+/// in it.
+fn subdirectory_of(dir: &std::path::Path, link: &[u8]) -> Option<std::path::PathBuf> {
+    let link = std::path::Path::new(std::ffi::OsStr::from_bytes(link));
+    let relative = link.strip_prefix(dir).ok()?;
+    let mut components = relative.components();
+    let first = components.next()?;
+    Some(match components.next() {
+        Some(_) => dir.join(first),
+        None => dir.to_owned(),
+    })
+}
+
+/// Gathers gc roots in one root per group, as chosen by `grouping`. Roots which do not
+/// belong to any group, like roots outside of the directory of `RootGrouping::Directory`,
+/// are left alone.
+/// noop if the graph is rooted in a fs node
+pub fn group_roots_by(di: DepInfos, grouping: &RootGrouping) -> DepInfos {
+    match grouping {
+        RootGrouping::Owner => group_roots(
+            di,
+            |description| gcroots::owner_of(description.path()?),
+            |&uid, _, _| {
+                NodeDescription::Group(format!("{}'s roots", gcroots::user_name(uid)).into_bytes())
+            },
+        ),
+        RootGrouping::Category => group_roots(di, NodeDescription::category, |category, _, _| {
+            NodeDescription::Group(format!("{category} roots").into_bytes())
+        }),
+        RootGrouping::Directory(dir) => group_roots(
+            di,
+            |description| match description {
+                NodeDescription::Link(path) => subdirectory_of(dir, path),
+                _ => None,
+            },
+            |subdir, _, _| NodeDescription::Group(subdir.as_os_str().as_bytes().to_vec()),
+        ),
+    }
+}
+
 /// Transitive reduction
 ///
 /// Handles cycles by removing back edges first, then doing tred on the resulting dag, and then
@@ -199,6 +361,7 @@ pub fn condense(mut di: DepInfos) -> DepInfos {
         });
         let new_w = &mut new_graph[*new_node];
         new_w.size = new_w.size + di.graph[idx].size;
+        new_w.merge_registration_time(di.graph[idx].registration_time());
     }
 
     let new_root = new_ids[&classes[di.root.index()]];
@@ -224,6 +387,20 @@ pub fn condense(mut di: DepInfos) -> DepInfos {
     di
 }
 
+/// The size above which approximately the `n` largest nodes of `di` are, for `keep`: 0 if
+/// there are at most `n` nodes.
+pub fn min_size_for_nodes(di: &DepInfos, n: usize) -> u64 {
+    if n >= di.graph.node_count() {
+        return 0;
+    }
+    if n == 0 {
+        return u64::MAX;
+    }
+    let mut sizes: Vec<u64> = di.graph.raw_nodes().iter().map(|n| n.weight.size).collect();
+    sizes.sort_unstable();
+    sizes[sizes.len() - n]
+}
+
 /// Creates a new graph retaining only reachable nodes
 pub fn keep_reachable(mut di: DepInfos) -> DepInfos {
     let mut new_graph = DepGraph::new();
@@ -344,6 +521,7 @@ pub fn keep<T: Fn(&DepNode) -> bool>(mut di: DepInfos, filter: T) -> DepInfos {
                                 &mut new_graph[old_id.unwrap_or_else(|| new_ids[&old])]
                             });
                         wup.size = wup.size + di.graph[idx].size;
+                        wup.merge_registration_time(di.graph[idx].registration_time());
                         di.graph[idx].size = 0;
                     }
                 }
@@ -367,6 +545,7 @@ pub fn keep<T: Fn(&DepNode) -> bool>(mut di: DepInfos, filter: T) -> DepInfos {
         let fake_root = DepNode {
             description: NodeDescription::FilteredOut,
             size: remaining_size,
+            info: None,
         };
         let id = new_graph.add_node(fake_root);
         new_graph.add_edge(new_root, id, ());
@@ -459,7 +638,11 @@ mod tests {
             } else {
                 3 + 2 * (i as u64)
             };
-            let w = DepNode { description, size };
+            let w = DepNode {
+                description,
+                size,
+                info: None,
+            };
             g.add_node(w);
         }
         for i in 0..size {
@@ -478,6 +661,7 @@ mod tests {
             DepNode {
                 description: Path("root".into()),
                 size: 42,
+                info: None,
             }
         } else {
             DepNode::dummy()
@@ -613,6 +797,177 @@ mod tests {
             }
         }
     }
+    /// a `DepInfos` where all nodes of `g` are reachable from `root`
+    fn connected(graph: DepGraph, root: NodeIndex) -> DepInfos {
+        let mut di = DepInfos {
+            graph,
+            root,
+            metadata: SizeMetadata {
+                reachable: Reachability::Connected,
+                dedup: DedupAwareness::Unaware,
+                size: enum_map! { _ => enum_map!{ _ => None }},
+            },
+        };
+        di.record_metadata();
+        di
+    }
+    #[test]
+    fn check_merge_process_roots() {
+        use self::NodeDescription::*;
+        let mut g = DepGraph::new();
+        let mut add = |description, size| {
+            g.add_node(DepNode {
+                description,
+                size,
+                info: None,
+            })
+        };
+        let root = add(Dummy, 0);
+        let a = add(Path(b"/nix/store/aaaa-a".to_vec()), 1);
+        let b = add(Path(b"/nix/store/bbbb-b".to_vec()), 10);
+        let c = add(Path(b"/nix/store/cccc-c".to_vec()), 100);
+        let maps = add(Memory(b"/proc/1/maps".to_vec()), 0);
+        let exe = add(Memory(b"/proc/1/exe".to_vec()), 0);
+        let fd = add(Memory(b"/proc/2/fd/3".to_vec()), 0);
+        let censored = add(Memory(b"{censored}".to_vec()), 0);
+        let link = add(Link(b"/home/user/result".to_vec()), 0);
+        for &(from, to) in &[(maps, a), (exe, b), (fd, a), (censored, b), (link, c)] {
+            g.add_edge(root, from, ());
+            g.add_edge(from, to, ());
+        }
+        let di = merge_process_roots(connected(g, root), |pid| format!("p{pid}").into_bytes());
+        di.check_metadata();
+        assert_eq!(
+            di.roots_name(),
+            ["p1", "p2", "{censored}", "/home/user/result"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect()
+        );
+        let di = condense(merge_transient_roots(di));
+        // a is shared by both processes, and b by p1 and {censored}
+        let sizes: BTreeMap<String, u64> = di
+            .roots()
+            .map(|idx| {
+                let w = &di.graph[idx];
+                (String::from_utf8_lossy(&w.name()).into_owned(), w.size)
+            })
+            .collect();
+        assert_eq!(sizes["p1"], 0);
+        assert_eq!(sizes["p2"], 0);
+        assert_eq!(sizes["{transient}"], 0);
+        assert_eq!(sizes["/home/user/result"], 100);
+        assert_eq!(di.reachable_size(), 111);
+    }
+    #[test]
+    fn check_merge_build_roots() {
+        use self::NodeDescription::*;
+        let mut g = DepGraph::new();
+        let mut add = |description, size| {
+            g.add_node(DepNode {
+                description,
+                size,
+                info: None,
+            })
+        };
+        let root = add(Dummy, 0);
+        let foo_drv = add(Path(b"/nix/store/aaaa-foo.drv".to_vec()), 1);
+        let bar_drv = add(Path(b"/nix/store/bbbb-bar.drv".to_vec()), 1);
+        let bar = add(Path(b"/nix/store/cccc-bar".to_vec()), 10);
+        let baz_drv = add(Path(b"/nix/store/dddd-baz.drv".to_vec()), 1);
+        let qux_drv = add(Path(b"/nix/store/eeee-qux.drv".to_vec()), 1);
+        let temp5 = add(Temporary(b"{temp:5}".to_vec()), 0);
+        let temp6 = add(Temporary(b"{temp:6}".to_vec()), 0);
+        let memory = add(Memory(b"/proc/7/maps".to_vec()), 0);
+        for &(from, to) in &[
+            (root, temp5),
+            (root, temp6),
+            (root, memory),
+            (foo_drv, bar_drv),
+            (temp5, foo_drv),
+            (temp5, bar_drv),
+            (temp5, bar),
+            (temp6, baz_drv),
+            (temp6, qux_drv),
+            (memory, bar),
+        ] {
+            g.add_edge(from, to, ());
+        }
+        let di = merge_build_roots(connected(g, root), |pid| format!("p{pid}").into_bytes());
+        di.check_metadata();
+        let names = di.roots_name();
+        assert!(names.contains("p5, building foo.drv"), "{:?}", names);
+        assert!(
+            names.contains("p6, building baz.drv and 1 more"),
+            "{:?}",
+            names
+        );
+        assert!(names.contains("/proc/7/maps"), "{:?}", names);
+        assert_eq!(names.len(), 3);
+        // builds are not transient roots
+        let di = merge_transient_roots(di);
+        assert_eq!(di.roots().count(), 3);
+    }
+    #[test]
+    fn check_group_roots_by() {
+        use self::NodeDescription::*;
+        let mut g = DepGraph::new();
+        let mut add = |description, size| {
+            g.add_node(DepNode {
+                description,
+                size,
+                info: None,
+            })
+        };
+        let root = add(Dummy, 0);
+        let foo = add(Path(b"/nix/store/aaaa-foo".to_vec()), 1);
+        let bar = add(Path(b"/nix/store/bbbb-bar".to_vec()), 10);
+        let links: Vec<_> = [
+            "/home/u/src/a/result",
+            "/home/u/src/a/.direnv/flake-profile",
+            "/home/u/src/b/result",
+            "/home/u/result",
+        ]
+        .iter()
+        .map(|link| add(Link(link.as_bytes().to_vec()), 0))
+        .collect();
+        let memory = add(Memory(b"/proc/7/maps".to_vec()), 0);
+        for &link in &links {
+            g.add_edge(root, link, ());
+            g.add_edge(link, foo, ());
+        }
+        g.add_edge(root, memory, ());
+        g.add_edge(memory, bar, ());
+        let di = connected(g, root);
+
+        let by_dir = group_roots_by(di.clone(), &"/home/u/src".parse().unwrap());
+        by_dir.check_metadata();
+        assert_eq!(
+            by_dir.roots_name(),
+            [
+                "/home/u/src/a",
+                "/home/u/src/b",
+                "/home/u/result",
+                "/proc/7/maps"
+            ]
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+        );
+        // one node per group
+        assert_eq!(condense(by_dir).graph.node_count(), 1 + 4 + 1);
+
+        let by_category = group_roots_by(di, &RootGrouping::Category);
+        by_category.check_metadata();
+        assert_eq!(
+            by_category.roots_name(),
+            ["memory roots", "nix-direnv roots", "other roots"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect()
+        );
+        assert!("src".parse::<RootGrouping>().is_err());
+    }
     #[test]
     fn check_keep_reachable() {
         for _ in 0..40 {
@@ -0,0 +1,1264 @@
+// SPDX-License-Identifier: LGPL-3.0
+
+//! Textual reports about the live store paths, printed with `--report` instead of the
+//! graph.
+
+use crate::depgraph::*;
+use crate::gcroots;
+use crate::msg::*;
+use crate::profile::{self, Element};
+use crate::sysroot;
+use bytesize::ByteSize;
+use fixedbitset::FixedBitSet;
+use petgraph::prelude::NodeIndex;
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, PathBuf};
+
+/// The live store paths sharing a package name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Package {
+    pub pname: String,
+    /// distinct versions, without the name of the output
+    pub versions: BTreeSet<String>,
+    pub paths: Vec<NodeIndex>,
+    /// sum of the size of the paths
+    pub total: u64,
+    /// what would be freed if none of these paths were alive: the paths themselves and
+    /// their dependencies which nothing else keeps alive
+    pub unique: u64,
+}
+
+/// Names of outputs of derivations, as found at the end of the names of store paths
+const OUTPUTS: &[&[u8]] = &[
+    b"bin",
+    b"dev",
+    b"devdoc",
+    b"doc",
+    b"info",
+    b"lib",
+    b"man",
+    b"out",
+    b"debug",
+    b"static",
+    b"modules",
+    b"terminfo",
+    b"examples",
+];
+
+/// Removes the name of the output from a version, like `3.11.6-dev`.
+fn strip_output(version: &[u8]) -> &[u8] {
+    match version.iter().rposition(|&c| c == b'-') {
+        Some(i) if OUTPUTS.contains(&&version[i + 1..]) => &version[..i],
+        _ => version,
+    }
+}
+
+/// Groups the store paths of `di` by package name, the heaviest packages first.
+///
+/// Requires that all nodes are reachable from the root.
+pub fn packages(di: &DepInfos) -> Vec<Package> {
+    let mut by_name = BTreeMap::<Path, Vec<NodeIndex>>::new();
+    for idx in di.graph.node_indices() {
+        if di.graph[idx].kind() == NodeKind::Path {
+            let name = di.graph[idx].name();
+            let (pname, _) = split_name(&name);
+            by_name.entry(pname.to_vec()).or_default().push(idx);
+        }
+    }
+    let (pnames, paths): (Vec<_>, Vec<_>) = by_name.into_iter().unzip();
+    let unique = di.exclusive_sizes_of(&paths);
+    let mut res: Vec<Package> = pnames
+        .into_par_iter()
+        .zip(paths)
+        .zip(unique)
+        .map(|((pname, paths), unique)| {
+            let versions = paths
+                .iter()
+                .map(|&idx| {
+                    let name = di.graph[idx].name();
+                    let (_, version) = split_name(&name);
+                    String::from_utf8_lossy(strip_output(version)).into_owned()
+                })
+                .collect();
+            Package {
+                pname: String::from_utf8_lossy(&pname).into_owned(),
+                versions,
+                total: paths.iter().map(|&idx| di.graph[idx].size).sum(),
+                paths,
+                unique,
+            }
+        })
+        .collect();
+    res.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.pname.cmp(&b.pname)));
+    res
+}
+
+/// Prints one line per package: name, number of versions, total and unique size, and the
+/// list of versions.
+pub fn print_packages<W: Write>(packages: &[Package], w: &mut W) -> io::Result<()> {
+    let width = packages
+        .iter()
+        .map(|p| p.pname.chars().count())
+        .chain(std::iter::once("package".len()))
+        .max()
+        .unwrap_or(0);
+    writeln!(
+        w,
+        "{:<width$}  {:>8}  {:>10}  {:>10}  versions",
+        "package", "count", "total", "unique"
+    )?;
+    for package in packages {
+        let versions: Vec<&str> = package
+            .versions
+            .iter()
+            .map(|v| if v.is_empty() { "-" } else { v.as_str() })
+            .collect();
+        writeln!(
+            w,
+            "{:<width$}  {:>8}  {:>10}  {:>10}  {}",
+            package.pname,
+            package.versions.len(),
+            ByteSize::b(package.total).to_string(),
+            ByteSize::b(package.unique).to_string(),
+            versions.join(", ")
+        )?;
+    }
+    Ok(())
+}
+
+/// How much space an element of a profile generation takes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElementUsage {
+    pub name: String,
+    /// the size of the closure of the element
+    pub closure: u64,
+    /// what removing this element from the generation would free
+    pub exclusive: u64,
+    /// the part of the closure other elements also need
+    pub shared: u64,
+}
+
+fn size_of(di: &DepInfos, nodes: &FixedBitSet) -> u64 {
+    nodes.ones().map(|i| di.graph[NodeIndex::new(i)].size).sum()
+}
+
+/// The usage of each of the `elements` of the profile generation whose node is
+/// `generation`, the heaviest first. Store paths absent from the graph are ignored.
+pub fn profile_elements(
+    di: &DepInfos,
+    generation: NodeIndex,
+    elements: &[Element],
+) -> Vec<ElementUsage> {
+    let index: HashMap<&[u8], NodeIndex> = di
+        .graph
+        .node_indices()
+        .filter(|&idx| di.graph[idx].kind() == NodeKind::Path)
+        .filter_map(|idx| Some((di.graph[idx].description.path()?.as_slice(), idx)))
+        .collect();
+    let nothing = FixedBitSet::with_capacity(di.graph.node_count());
+    let total = size_of(di, &di.reachable_without(generation, &nothing));
+    let mut res: Vec<ElementUsage> = elements
+        .par_iter()
+        .map(|element| {
+            let paths: Vec<NodeIndex> = element
+                .store_paths
+                .iter()
+                .filter_map(|p| index.get(p.as_slice()).copied())
+                .collect();
+            let mut removed = FixedBitSet::with_capacity(di.graph.node_count());
+            for idx in &paths {
+                removed.insert(idx.index());
+            }
+            let exclusive = total - size_of(di, &di.reachable_without(generation, &removed));
+            let mut dfs = petgraph::visit::Dfs::empty(&di.graph);
+            for &idx in &paths {
+                dfs.move_to(idx);
+                while dfs.next(&di.graph).is_some() {}
+            }
+            let closure = size_of(di, &dfs.discovered);
+            ElementUsage {
+                name: element.name.clone(),
+                closure,
+                exclusive,
+                shared: closure - exclusive,
+            }
+        })
+        .collect();
+    res.sort_by(|a, b| {
+        (b.exclusive, b.closure)
+            .cmp(&(a.exclusive, a.closure))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    res
+}
+
+/// The store paths of the roots of `di` which may be profile generations: the root
+/// itself when the graph is the closure of a single path, the targets of the roots
+/// otherwise.
+fn root_paths(di: &DepInfos) -> Vec<NodeIndex> {
+    if di.graph[di.root].kind() == NodeKind::Path {
+        return vec![di.root];
+    }
+    let mut res: Vec<_> = di
+        .roots()
+        .flat_map(|idx| match di.graph[idx].kind() {
+            NodeKind::Path => vec![idx],
+            _ => di
+                .graph
+                .neighbors(idx)
+                .filter(|&child| di.graph[child].kind() == NodeKind::Path)
+                .collect(),
+        })
+        .collect();
+    res.sort();
+    res.dedup();
+    res
+}
+
+/// For each profile generation among the roots, prints one line per element: name, size
+/// of its closure, what removing it would free and what it shares with other elements.
+///
+/// Returns the number of profile generations found.
+pub fn print_profiles<W: Write>(di: &DepInfos, w: &mut W) -> io::Result<usize> {
+    let mut found = 0;
+    for generation in root_paths(di) {
+        let path = di.graph[generation].description.path().unwrap();
+        let elements = match profile::read_manifest(path) {
+            Ok(Some(elements)) => elements,
+            Ok(None) => continue,
+            Err(e) => {
+                msg!(
+                    "warning: could not read the manifest of {}: {}\n",
+                    String::from_utf8_lossy(path),
+                    e
+                );
+                continue;
+            }
+        };
+        if found > 0 {
+            writeln!(w)?;
+        }
+        found += 1;
+        let referrers: Vec<_> = di
+            .graph
+            .neighbors_directed(generation, petgraph::Direction::Incoming)
+            .filter(|&idx| di.graph[idx].kind() == NodeKind::Link)
+            .map(|idx| String::from_utf8_lossy(&di.graph[idx].name()).into_owned())
+            .collect();
+        write!(w, "{}", String::from_utf8_lossy(path))?;
+        if !referrers.is_empty() {
+            write!(w, " ({})", referrers.join(", "))?;
+        }
+        writeln!(w, ":")?;
+        let usages = profile_elements(di, generation, &elements);
+        let width = usages
+            .iter()
+            .map(|u| u.name.chars().count())
+            .chain(std::iter::once("element".len()))
+            .max()
+            .unwrap_or(0);
+        writeln!(
+            w,
+            "{:<width$}  {:>10}  {:>10}  {:>10}",
+            "element", "closure", "exclusive", "shared"
+        )?;
+        for usage in usages {
+            writeln!(
+                w,
+                "{:<width$}  {:>10}  {:>10}  {:>10}",
+                usage.name,
+                ByteSize::b(usage.closure).to_string(),
+                ByteSize::b(usage.exclusive).to_string(),
+                ByteSize::b(usage.shared).to_string(),
+            )?;
+        }
+    }
+    Ok(found)
+}
+
+/// A gc root which looks forgotten
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrphanRoot {
+    pub root: NodeIndex,
+    pub reason: Orphan,
+    /// what removing this root alone would free
+    pub alone: u64,
+}
+
+/// The gc roots of `di` which look forgotten, see `NodeDescription::orphan`, the heaviest
+/// first, and what removing all of them would free.
+///
+/// Requires that all nodes are reachable from the root.
+pub fn orphans(di: &DepInfos, stale_after: std::time::Duration) -> (Vec<OrphanRoot>, u64) {
+    let exclusive = di.exclusive_sizes();
+    let mut res: Vec<OrphanRoot> = di
+        .roots()
+        .filter_map(|idx| {
+            let reason = di.graph[idx].description.orphan(stale_after)?;
+            Some(OrphanRoot {
+                root: idx,
+                reason,
+                alone: exclusive[idx.index()],
+            })
+        })
+        .collect();
+    res.sort_by(|a, b| b.alone.cmp(&a.alone).then_with(|| a.root.cmp(&b.root)));
+    let mut removed = FixedBitSet::with_capacity(di.graph.node_count());
+    for orphan in &res {
+        removed.insert(orphan.root.index());
+    }
+    let together = di.size() - size_of(di, &di.reachable_without(di.root, &removed));
+    (res, together)
+}
+
+/// Prints one line per forgotten root: its path, why it looks forgotten and what it keeps
+/// alive alone, then the entries of `gcroots/auto` which point nowhere.
+pub fn print_orphans<W: Write>(
+    di: &DepInfos,
+    orphans: &[OrphanRoot],
+    together: u64,
+    dangling: &[gcroots::Root],
+    w: &mut W,
+) -> io::Result<()> {
+    let path = |orphan: &OrphanRoot| {
+        String::from_utf8_lossy(di.graph[orphan.root].description.path().unwrap()).into_owned()
+    };
+    let width = orphans
+        .iter()
+        .map(|o| path(o).chars().count())
+        .chain(std::iter::once("root".len()))
+        .max()
+        .unwrap_or(0);
+    writeln!(w, "{:<width$}  {:>10}  reason", "root", "alone")?;
+    for orphan in orphans {
+        writeln!(
+            w,
+            "{:<width$}  {:>10}  {}",
+            path(orphan),
+            ByteSize::b(orphan.alone).to_string(),
+            orphan.reason,
+        )?;
+    }
+    writeln!(
+        w,
+        "removing these {} roots would free {}",
+        orphans.len(),
+        ByteSize::b(together)
+    )?;
+    if !dangling.is_empty() {
+        writeln!(
+            w,
+            "\n{} indirect roots point to deleted links and keep nothing alive:",
+            dangling.len()
+        )?;
+        for (link, target) in dangling {
+            writeln!(
+                w,
+                "{} -> {}",
+                String::from_utf8_lossy(link),
+                String::from_utf8_lossy(target)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// What garbage collection would free once some gc roots are removed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deletion {
+    /// the exact number of bytes freed
+    pub freed: u64,
+    /// the store paths which would be deleted, the largest first
+    pub paths: Vec<NodeIndex>,
+}
+
+/// The gc roots of `di` designated by `path`, as typed by the user: relative to the
+/// current directory, or through symlinks or `..`.
+///
+/// The link itself is what gets deleted, so only its directory is canonicalized. If
+/// `local` is false, the roots are those of another machine and cannot be canonicalized
+/// here, so `..` is only removed lexically.
+pub fn find_roots_to_delete(di: &DepInfos, path: &std::path::Path, local: bool) -> Vec<NodeIndex> {
+    let path_of = |idx: NodeIndex| {
+        di.graph[idx]
+            .description
+            .path()
+            .map(|path| std::path::Path::new(std::ffi::OsStr::from_bytes(path)))
+    };
+    let find = |wanted: &dyn Fn(&std::path::Path) -> bool| -> Vec<NodeIndex> {
+        di.roots()
+            .filter(|&idx| path_of(idx).is_some_and(wanted))
+            .collect()
+    };
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_owned());
+    let found = find(&|root| root == path);
+    if !found.is_empty() {
+        return found;
+    }
+    if local {
+        let canonical = |path: &std::path::Path| {
+            let parent = sysroot::canonicalize(path.parent()?).ok()?;
+            Some(parent.join(path.file_name()?))
+        };
+        match canonical(&path) {
+            Some(path) => find(&|root| canonical(root).as_ref() == Some(&path)),
+            None => Vec::new(),
+        }
+    } else {
+        let mut lexical = PathBuf::new();
+        for component in path.components() {
+            match component {
+                Component::ParentDir => {
+                    lexical.pop();
+                }
+                c => lexical.push(c),
+            }
+        }
+        find(&|root| root == lexical)
+    }
+}
+
+/// Simulates the removal of the gc roots `roots`: everything they alone keep alive is
+/// deleted. When the graph is aware of store optimisation, files whose hard links are
+/// still alive elsewhere are not counted as freed.
+///
+/// Requires that all nodes are reachable from the root.
+pub fn simulate_delete(di: &DepInfos, roots: &[NodeIndex]) -> Deletion {
+    let mut removed = FixedBitSet::with_capacity(di.graph.node_count());
+    for idx in roots {
+        removed.insert(idx.index());
+    }
+    let alive = di.reachable_without(di.root, &removed);
+    let dead = || {
+        di.graph
+            .node_indices()
+            .filter(|&idx| !alive.contains(idx.index()))
+    };
+    let freed = dead().map(|idx| di.graph[idx].size).sum();
+    let mut paths: Vec<NodeIndex> = dead()
+        .filter(|&idx| di.graph[idx].kind() == NodeKind::Path)
+        .collect();
+    paths.sort_by(|&a, &b| {
+        di.graph[b]
+            .size
+            .cmp(&di.graph[a].size)
+            .then_with(|| a.cmp(&b))
+    });
+    Deletion { freed, paths }
+}
+
+/// Prints what deleting some roots would free, and the `limit` largest store paths
+/// which would go.
+pub fn print_deletion<W: Write>(
+    di: &DepInfos,
+    deletion: &Deletion,
+    limit: usize,
+    w: &mut W,
+) -> io::Result<()> {
+    writeln!(
+        w,
+        "garbage collection would free {} ({} bytes) in {} store paths",
+        ByteSize::b(deletion.freed),
+        deletion.freed,
+        deletion.paths.len()
+    )?;
+    if di.metadata.dedup == DedupAwareness::Unaware {
+        writeln!(
+            w,
+            "store optimisation was not taken into account, the actual figure may be lower"
+        )?;
+    }
+    for &idx in deletion.paths.iter().take(limit) {
+        writeln!(
+            w,
+            "{:>10}  {}",
+            ByteSize::b(di.graph[idx].size).to_string(),
+            String::from_utf8_lossy(di.graph[idx].description.path().unwrap())
+        )?;
+    }
+    if deletion.paths.len() > limit {
+        writeln!(w, "and {} smaller ones", deletion.paths.len() - limit)?;
+    }
+    Ok(())
+}
+
+/// For each node of `di`, the set of the `roots` it depends on, as indices in `roots`.
+fn roots_reaching(di: &DepInfos, roots: &[NodeIndex]) -> Vec<FixedBitSet> {
+    let mut reach = vec![FixedBitSet::with_capacity(roots.len()); di.graph.node_count()];
+    for (i, &root) in roots.iter().enumerate() {
+        let mut dfs = petgraph::visit::Dfs::new(&di.graph, root);
+        while let Some(idx) = dfs.next(&di.graph) {
+            reach[idx.index()].insert(i);
+        }
+    }
+    reach
+}
+
+/// A step of `recommend_deletions`: roots to delete together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pick {
+    pub roots: Vec<NodeIndex>,
+    /// what deleting these roots frees, once the previous picks are deleted
+    pub freed: u64,
+    /// what deleting these roots and the previous picks frees
+    pub cumulative: u64,
+}
+
+/// Chooses gc roots to delete to free at least `target` bytes, greedily by bytes freed per
+/// root deleted. Only links can be deleted, and protected ones (see
+/// `NodeDescription::is_protected`) are never chosen. With `protect_latest`, for roots
+/// found on another machine, the latest generation of each profile is assumed to be the
+/// current one and is never chosen either. Stops early if nothing else can be freed.
+///
+/// `di` should be condensed: a class is freed once all the roots it depends on are gone.
+pub fn recommend_deletions(di: &DepInfos, target: u64, protect_latest: bool) -> Vec<Pick> {
+    let roots: Vec<NodeIndex> = di.roots().collect();
+    let mut latest: HashMap<&[u8], u64> = HashMap::new();
+    if protect_latest {
+        for &idx in &roots {
+            let generation = di.graph[idx]
+                .description
+                .path()
+                .and_then(|path| profile_generation(path));
+            if let Some((profile, number)) = generation {
+                let max = latest.entry(profile).or_insert(number);
+                *max = number.max(*max);
+            }
+        }
+    }
+    let is_latest = |path: &[u8]| {
+        profile_generation(path)
+            .is_some_and(|(profile, number)| latest.get(profile) == Some(&number))
+    };
+    let deletable: FixedBitSet = roots
+        .iter()
+        .enumerate()
+        .filter(|&(_, &idx)| {
+            let description = &di.graph[idx].description;
+            description.kind() == NodeKind::Link
+                && !description.is_protected()
+                && !description.path().is_some_and(|path| is_latest(path))
+        })
+        .map(|(i, _)| i)
+        .collect();
+    let reach = roots_reaching(di, &roots);
+    // the classes which deleting some roots can free, grouped by the roots they depend on
+    let mut groups = merge_equal_roots(di.graph.node_indices().filter_map(|idx| {
+        let r = &reach[idx.index()];
+        r.is_subset(&deletable)
+            .then(|| (r.clone(), di.graph[idx].size))
+    }));
+    let mut cumulative = 0;
+    let mut res = Vec::new();
+    while cumulative < target {
+        // (freed per root, freed, group whose roots to delete)
+        let mut best: Option<(u64, u64, usize)> = None;
+        for (i, (added, _)) in groups.iter().enumerate() {
+            let freed: u64 = groups
+                .iter()
+                .filter(|(r, _)| r.is_subset(added))
+                .map(|&(_, size)| size)
+                .sum();
+            let per_root = freed / added.count_ones(..) as u64;
+            if best.is_none_or(|(p, f, _)| (per_root, freed) > (p, f)) {
+                best = Some((per_root, freed, i));
+            }
+        }
+        let (_, freed, i) = match best {
+            Some(best) => best,
+            None => break,
+        };
+        // the groups now only depend on the roots not chosen yet
+        let added = groups[i].0.clone();
+        groups = merge_equal_roots(groups.into_iter().map(|(mut r, size)| {
+            r.difference_with(&added);
+            (r, size)
+        }));
+        cumulative += freed;
+        res.push(Pick {
+            roots: added.ones().map(|i| roots[i]).collect(),
+            freed,
+            cumulative,
+        });
+    }
+    res
+}
+
+/// Merges the sets of roots which are equal, summing their sizes, in order of first
+/// appearance. Empty sets are dropped.
+fn merge_equal_roots(sets: impl Iterator<Item = (FixedBitSet, u64)>) -> Vec<(FixedBitSet, u64)> {
+    let mut position: HashMap<FixedBitSet, usize> = HashMap::new();
+    let mut res: Vec<(FixedBitSet, u64)> = Vec::new();
+    for (set, size) in sets {
+        if set.is_clear() {
+            continue;
+        }
+        match position.get(&set) {
+            Some(&i) => res[i].1 += size,
+            None => {
+                position.insert(set.clone(), res.len());
+                res.push((set, size));
+            }
+        }
+    }
+    res
+}
+
+/// Prints the roots chosen by `recommend_deletions`, one per line, with what each pick
+/// frees and the cumulative freed size.
+pub fn print_recommendation<W: Write>(
+    di: &DepInfos,
+    picks: &[Pick],
+    target: u64,
+    w: &mut W,
+) -> io::Result<()> {
+    writeln!(
+        w,
+        "{:>4}  {:>10}  {:>10}  root",
+        "rank", "frees", "cumulative"
+    )?;
+    for (rank, pick) in picks.iter().enumerate() {
+        for (i, &root) in pick.roots.iter().enumerate() {
+            let path = String::from_utf8_lossy(di.graph[root].description.path().unwrap());
+            let name = di.graph[root].name();
+            let label = if name.starts_with(path.as_bytes()) {
+                String::new()
+            } else {
+                format!(" ({})", String::from_utf8_lossy(&name))
+            };
+            if i == 0 {
+                writeln!(
+                    w,
+                    "{:>4}  {:>10}  {:>10}  {}{}",
+                    rank + 1,
+                    ByteSize::b(pick.freed).to_string(),
+                    ByteSize::b(pick.cumulative).to_string(),
+                    path,
+                    label
+                )?;
+            } else {
+                writeln!(w, "{:>4}  {:>10}  {:>10}  {}{}", "", "", "", path, label)?;
+            }
+        }
+    }
+    let total = picks.last().map_or(0, |p| p.cumulative);
+    if total < target {
+        writeln!(
+            w,
+            "only {} can be freed without deleting protected or in-memory roots",
+            ByteSize::b(total)
+        )?;
+    }
+    Ok(())
+}
+
+/// How much space a gc root takes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootUsage {
+    pub root: NodeIndex,
+    /// what only this root keeps alive
+    pub exclusive: u64,
+    /// the size of everything this root keeps alive
+    pub closure: u64,
+    /// the part of the closure other roots also keep alive
+    pub shared: u64,
+    pub age: Age,
+}
+
+/// The column `sort_roots` sorts by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootColumn {
+    Exclusive,
+    Closure,
+    Shared,
+    Age,
+    Name,
+}
+
+/// The usage of each gc root of `di`.
+///
+/// Requires that all nodes are reachable from the root.
+pub fn root_usages(di: &DepInfos) -> Vec<RootUsage> {
+    let exclusive = di.exclusive_sizes();
+    let roots: Vec<NodeIndex> = di.roots().collect();
+    roots
+        .into_par_iter()
+        .map(|root| {
+            let mut dfs = petgraph::visit::Dfs::new(&di.graph, root);
+            while dfs.next(&di.graph).is_some() {}
+            let closure = size_of(di, &dfs.discovered);
+            let exclusive = exclusive[root.index()];
+            RootUsage {
+                root,
+                exclusive,
+                closure,
+                shared: closure - exclusive,
+                age: di.graph[root].description.link_age(),
+            }
+        })
+        .collect()
+}
+
+/// Sorts roots by `column`: largest sizes, oldest links, or names in alphabetical order
+/// first.
+pub fn sort_roots(di: &DepInfos, usages: &mut [RootUsage], column: RootColumn) {
+    let name = |u: &RootUsage| di.graph[u.root].description.label().into_owned();
+    match column {
+        RootColumn::Exclusive => usages.sort_by_key(|u| std::cmp::Reverse(u.exclusive)),
+        RootColumn::Closure => usages.sort_by_key(|u| std::cmp::Reverse(u.closure)),
+        RootColumn::Shared => usages.sort_by_key(|u| std::cmp::Reverse(u.shared)),
+        // links of unknown age last
+        RootColumn::Age => usages.sort_by_key(|u| (u.age.0.is_none(), u.age)),
+        RootColumn::Name => usages.sort_by_cached_key(name),
+    }
+}
+
+/// Prints one line per gc root: what it alone keeps alive, its closure, what it shares
+/// with other roots, the age of the link and its name.
+pub fn print_roots<W: Write>(di: &DepInfos, usages: &[RootUsage], w: &mut W) -> io::Result<()> {
+    writeln!(
+        w,
+        "{:>10}  {:>10}  {:>10}  {:>8}  root",
+        "exclusive", "closure", "shared", "age"
+    )?;
+    for usage in usages {
+        let age = usage.age.to_string();
+        let age = match age.trim_start_matches(", ") {
+            "" => "-",
+            age => age,
+        };
+        writeln!(
+            w,
+            "{:>10}  {:>10}  {:>10}  {:>8}  {}",
+            ByteSize::b(usage.exclusive).to_string(),
+            ByteSize::b(usage.closure).to_string(),
+            ByteSize::b(usage.shared).to_string(),
+            age,
+            String::from_utf8_lossy(&di.graph[usage.root].description.label())
+        )?;
+    }
+    Ok(())
+}
+
+/// The bytes kept alive by both roots of each pair of some gc roots
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overlap {
+    /// the oldest links first
+    pub roots: Vec<NodeIndex>,
+    /// `shared[i][j]` is kept alive by both `roots[i]` and `roots[j]`; `shared[i][i]` is
+    /// the closure of `roots[i]`
+    pub shared: Vec<Vec<u64>>,
+}
+
+impl Overlap {
+    /// How similar the closures of roots `i` and `j` are: the shared bytes over the bytes
+    /// kept alive by either of them, between 0 and 1.
+    pub fn similarity(&self, i: usize, j: usize) -> f64 {
+        let union = self.shared[i][i] + self.shared[j][j] - self.shared[i][j];
+        match union {
+            0 => 1.,
+            union => self.shared[i][j] as f64 / union as f64,
+        }
+    }
+}
+
+/// The overlap of the `limit` gc roots of `di` with the largest closure.
+///
+/// `di` should be condensed, for speed: only the set of roots each node depends on
+/// matters.
+pub fn overlap(di: &DepInfos, limit: usize) -> Overlap {
+    let all_roots: Vec<NodeIndex> = di.roots().collect();
+    let reach = roots_reaching(di, &all_roots);
+    let mut closures = vec![0; all_roots.len()];
+    for idx in di.graph.node_indices() {
+        for i in reach[idx.index()].ones() {
+            closures[i] += di.graph[idx].size;
+        }
+    }
+    let mut kept: Vec<usize> = (0..all_roots.len()).collect();
+    kept.sort_by_key(|&i| std::cmp::Reverse(closures[i]));
+    kept.truncate(limit);
+    kept.sort_by_cached_key(|&i| {
+        let description = &di.graph[all_roots[i]].description;
+        let age = description.link_age();
+        // generations of a profile with the same age are sorted by number, not by name
+        let label = description.label().into_owned();
+        let (profile, number) = match description.path().and_then(|p| profile_generation(p)) {
+            Some((profile, number)) => (profile.to_vec(), number),
+            None => (label.clone(), 0),
+        };
+        (age.0.is_none(), age, profile, number, label)
+    });
+    let mut shared = vec![vec![0; kept.len()]; kept.len()];
+    for idx in di.graph.node_indices() {
+        let size = di.graph[idx].size;
+        let r = &reach[idx.index()];
+        for (a, &i) in kept.iter().enumerate() {
+            if !r.contains(i) {
+                continue;
+            }
+            for (b, &j) in kept.iter().enumerate() {
+                if r.contains(j) {
+                    shared[a][b] += size;
+                }
+            }
+        }
+    }
+    Overlap {
+        roots: kept.into_iter().map(|i| all_roots[i]).collect(),
+        shared,
+    }
+}
+
+/// Quotes a field of a csv file if needed.
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Prints the overlap matrix as csv, in bytes, with the names of the roots as first row
+/// and first column.
+pub fn print_overlap_csv<W: Write>(di: &DepInfos, overlap: &Overlap, w: &mut W) -> io::Result<()> {
+    let names: Vec<String> = overlap
+        .roots
+        .iter()
+        .map(|&idx| String::from_utf8_lossy(&di.graph[idx].name()).into_owned())
+        .collect();
+    write!(w, "root")?;
+    for name in &names {
+        write!(w, ",{}", csv_field(name))?;
+    }
+    writeln!(w)?;
+    for (name, row) in names.iter().zip(&overlap.shared) {
+        write!(w, "{}", csv_field(name))?;
+        for bytes in row {
+            write!(w, ",{bytes}")?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MemoryStore;
+    use crate::testutil::{add, store_path, TempDir};
+
+    #[test]
+    fn python_versions() {
+        let mut store = MemoryStore::new();
+        let (env1, env2) = (add(&mut store, "env1", 1), add(&mut store, "env2", 1));
+        let py39 = add(&mut store, "python3-3.9.18", 100);
+        let py311 = add(&mut store, "python3-3.11.6", 200);
+        let py311_dev = add(&mut store, "python3-3.11.6-dev", 10);
+        let libffi = add(&mut store, "libffi-3.4", 1000);
+        let openssl = add(&mut store, "openssl-3.0", 10000);
+        let beta = add(&mut store, "foo-1.2-beta", 1);
+        let release = add(&mut store, "foo-1.3", 1);
+        for &(from, to) in &[
+            (env1, beta),
+            (env2, release),
+            (env1, py39),
+            (env2, py311),
+            (env2, py311_dev),
+            (py39, libffi),
+            (py311, libffi),
+            (py39, openssl),
+            (env1, openssl),
+        ] {
+            store.add_reference(from, to);
+        }
+        let di = crate::backend::read(store).unwrap();
+        let packages = packages(&di);
+        assert_eq!(packages[0].pname, "openssl");
+        assert_eq!(packages[0].unique, 10000);
+        let python = packages.iter().find(|p| p.pname == "python3").unwrap();
+        assert_eq!(
+            python.versions,
+            ["3.11.6", "3.9.18"].iter().map(|s| s.to_string()).collect()
+        );
+        assert_eq!(python.total, 310);
+        assert_eq!(python.unique, 1310);
+        let libffi = packages.iter().find(|p| p.pname == "libffi").unwrap();
+        assert_eq!(libffi.unique, 1000);
+        let foo = packages.iter().find(|p| p.pname == "foo").unwrap();
+        assert_eq!(
+            foo.versions,
+            ["1.2-beta", "1.3"].iter().map(|s| s.to_string()).collect()
+        );
+        assert_eq!(foo.unique, 2);
+
+        let mut out = Vec::new();
+        print_packages(&packages, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("package "), "{}", out);
+        assert_eq!(out.lines().count(), packages.len() + 1);
+    }
+
+    #[test]
+    fn profile_elements_usage() {
+        let mut store = MemoryStore::new();
+        let profile = add(&mut store, "profile", 1);
+        let hello = add(&mut store, "hello-2.12", 10);
+        let git = add(&mut store, "git-2.44.0", 100);
+        let git_doc = add(&mut store, "git-2.44.0-doc", 1000);
+        let glibc = add(&mut store, "glibc-2.39", 10000);
+        let curl = add(&mut store, "curl-8.6.0", 100000);
+        for &(from, to) in &[
+            (profile, hello),
+            (profile, git),
+            (profile, git_doc),
+            (hello, glibc),
+            (git, glibc),
+            (git, curl),
+        ] {
+            store.add_reference(from, to);
+        }
+        let di = crate::backend::read(store).unwrap();
+        let elements = vec![
+            Element {
+                name: "hello".into(),
+                store_paths: vec![store_path("hello-2.12")],
+            },
+            Element {
+                name: "git".into(),
+                store_paths: vec![store_path("git-2.44.0"), store_path("git-2.44.0-doc")],
+            },
+        ];
+        let profile = NodeIndex::new(profile);
+        let usages = profile_elements(&di, profile, &elements);
+        assert_eq!(
+            usages,
+            vec![
+                ElementUsage {
+                    name: "git".into(),
+                    closure: 111100,
+                    exclusive: 101100,
+                    shared: 10000,
+                },
+                ElementUsage {
+                    name: "hello".into(),
+                    closure: 10010,
+                    exclusive: 10,
+                    shared: 10000,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn deleted_roots() {
+        let mut store = MemoryStore::new();
+        let foo = store.add_path(b"/nix/store/aaaa-foo".to_vec(), 10);
+        let bar = store.add_path(b"/nix/store/aaaa-bar".to_vec(), 1000);
+        let glibc = store.add_path(b"/nix/store/aaaa-glibc".to_vec(), 100);
+        store.add_reference(foo, glibc);
+        store.add_reference(bar, glibc);
+        store.add_gc_roots(vec![
+            (
+                b"/nonexistent/nix-du/foo/result".to_vec(),
+                b"/nix/store/aaaa-foo".to_vec(),
+            ),
+            (
+                b"/nonexistent/nix-du/bar/result".to_vec(),
+                b"/nix/store/aaaa-bar".to_vec(),
+            ),
+            (
+                b"/nix/var/nix/profiles/system-1-link".to_vec(),
+                b"/nix/store/aaaa-glibc".to_vec(),
+            ),
+        ]);
+        let di = crate::reduction::keep_reachable(crate::backend::read(store).unwrap());
+        let (orphans, together) = orphans(&di, std::time::Duration::from_secs(0));
+        let summary: Vec<_> = orphans
+            .iter()
+            .map(|o| {
+                (
+                    di.graph[o.root].description.path().unwrap().clone(),
+                    o.alone,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (b"/nonexistent/nix-du/bar/result".to_vec(), 1000),
+                (b"/nonexistent/nix-du/foo/result".to_vec(), 10),
+            ]
+        );
+        assert!(orphans.iter().all(|o| o.reason == Orphan::Gone));
+        assert_eq!(together, 1010);
+    }
+
+    #[test]
+    fn deletion() {
+        let mut store = MemoryStore::new();
+        let gen1 = store.add_path(b"/nix/store/aaaa-system-1".to_vec(), 1);
+        let gen2 = store.add_path(b"/nix/store/bbbb-system-2".to_vec(), 2);
+        let old = store.add_path(b"/nix/store/cccc-linux-6.1".to_vec(), 100);
+        let new = store.add_path(b"/nix/store/dddd-linux-6.6".to_vec(), 200);
+        let glibc = store.add_path(b"/nix/store/eeee-glibc".to_vec(), 1000);
+        for &(from, to) in &[
+            (gen1, old),
+            (gen2, new),
+            (gen1, glibc),
+            (gen2, glibc),
+            (old, glibc),
+        ] {
+            store.add_reference(from, to);
+        }
+        store.add_gc_roots(vec![
+            (
+                b"/nix/var/nix/profiles/system-1-link".to_vec(),
+                b"/nix/store/aaaa-system-1".to_vec(),
+            ),
+            (
+                b"/nix/var/nix/profiles/system-2-link".to_vec(),
+                b"/nix/store/bbbb-system-2".to_vec(),
+            ),
+        ]);
+        let di = crate::reduction::keep_reachable(crate::backend::read(store).unwrap());
+        let root = |path: &[u8]| {
+            di.roots()
+                .find(|&idx| di.graph[idx].description.path().unwrap() == path)
+                .unwrap()
+        };
+        let gen1 = root(b"/nix/var/nix/profiles/system-1-link");
+        let deletion = simulate_delete(&di, &[gen1]);
+        assert_eq!(deletion.freed, 101);
+        let names: Vec<_> = deletion
+            .paths
+            .iter()
+            .map(|&idx| di.graph[idx].name().into_owned())
+            .collect();
+        assert_eq!(names, vec![b"linux-6.1".to_vec(), b"system-1".to_vec()]);
+        let gen2 = root(b"/nix/var/nix/profiles/system-2-link");
+        assert_eq!(simulate_delete(&di, &[gen1, gen2]).freed, 1303);
+    }
+
+    #[test]
+    fn roots_to_delete() {
+        let tmp = TempDir::new("delete");
+        std::fs::create_dir_all(tmp.join("home/u/project")).unwrap();
+        std::fs::create_dir_all(tmp.join("home/u/other")).unwrap();
+        std::os::unix::fs::symlink(tmp.join("home/u"), tmp.join("u")).unwrap();
+        let local = tmp.join("home/u/project/result");
+        let mut store = MemoryStore::new();
+        add(&mut store, "foo", 1);
+        add(&mut store, "bar", 1);
+        store.add_gc_roots(vec![
+            (local.as_os_str().as_bytes().to_vec(), store_path("foo")),
+            // a root of another machine, which does not exist here
+            (b"/home/u/result".to_vec(), store_path("bar")),
+        ]);
+        let di = crate::backend::read(store).unwrap();
+        let found = |path: &std::path::Path, local: bool| -> Vec<Vec<u8>> {
+            find_roots_to_delete(&di, path, local)
+                .into_iter()
+                .map(|idx| di.graph[idx].description.path().unwrap().clone())
+                .collect()
+        };
+        let expected = vec![local.as_os_str().as_bytes().to_vec()];
+        assert_eq!(found(&local, true), expected);
+        assert_eq!(
+            found(&tmp.join("home/u/other/../project/result"), true),
+            expected
+        );
+        assert_eq!(found(&tmp.join("u/project/result"), true), expected);
+        assert!(found(&tmp.join("home/u/other/result"), true).is_empty());
+        assert_eq!(
+            found(std::path::Path::new("/home/u/x/../result"), false),
+            vec![b"/home/u/result".to_vec()]
+        );
+        assert!(found(std::path::Path::new("/home/u/x/../other"), false).is_empty());
+    }
+
+    #[test]
+    fn recommendation() {
+        let mut store = MemoryStore::new();
+        add(&mut store, "a", 1000);
+        let b = add(&mut store, "b", 10);
+        let c = add(&mut store, "c", 10);
+        let shared = add(&mut store, "shared", 5000);
+        add(&mut store, "current", 100_000);
+        add(&mut store, "memory", 100_000);
+        add(&mut store, "system-9", 300);
+        add(&mut store, "system-10", 200);
+        store.add_reference(b, shared);
+        store.add_reference(c, shared);
+        store.add_gc_roots(
+            [
+                ("/home/u/a/result", "a"),
+                ("/home/u/b/result", "b"),
+                ("/home/u/c/result", "c"),
+                ("/run/current-system", "current"),
+                ("/srv/profiles/system-9-link", "system-9"),
+                ("/srv/profiles/system-10-link", "system-10"),
+                ("/proc/12/maps", "memory"),
+            ]
+            .iter()
+            .map(|(link, target)| {
+                (
+                    link.as_bytes().to_vec(),
+                    format!("/nix/store/aaaa-{target}").into_bytes(),
+                )
+            }),
+        );
+        let di = crate::reduction::condense(crate::reduction::keep_reachable(
+            crate::backend::read(store).unwrap(),
+        ));
+        let summary = |picks: Vec<Pick>| -> Vec<(Vec<String>, u64, u64)> {
+            picks
+                .into_iter()
+                .map(|pick| {
+                    let mut roots: Vec<_> = pick
+                        .roots
+                        .iter()
+                        .map(|&idx| {
+                            String::from_utf8_lossy(di.graph[idx].description.path().unwrap())
+                                .into_owned()
+                        })
+                        .collect();
+                    roots.sort();
+                    (roots, pick.freed, pick.cumulative)
+                })
+                .collect()
+        };
+        // b and c share 5000 bytes: 2510 per root is better than 1000 for a
+        assert_eq!(
+            summary(recommend_deletions(&di, 2000, false)),
+            vec![(
+                vec!["/home/u/b/result".into(), "/home/u/c/result".into()],
+                5020,
+                5020
+            )]
+        );
+        assert_eq!(
+            summary(recommend_deletions(&di, u64::MAX, false)),
+            vec![
+                (
+                    vec!["/home/u/b/result".into(), "/home/u/c/result".into()],
+                    5020,
+                    5020
+                ),
+                (vec!["/home/u/a/result".into()], 1000, 6020),
+                (vec!["/srv/profiles/system-9-link".into()], 300, 6320),
+                (vec!["/srv/profiles/system-10-link".into()], 200, 6520),
+            ]
+        );
+        // roots from another machine: system-10 may be the current generation
+        assert_eq!(
+            summary(recommend_deletions(&di, u64::MAX, true)).last(),
+            Some(&(vec!["/srv/profiles/system-9-link".into()], 300, 6320))
+        );
+    }
+
+    #[test]
+    fn roots_table() {
+        let mut store = MemoryStore::new();
+        let foo = add(&mut store, "foo", 10);
+        let bar = add(&mut store, "bar", 100);
+        let glibc = add(&mut store, "glibc", 1000);
+        store.add_reference(foo, glibc);
+        store.add_reference(bar, glibc);
+        store.add_gc_roots(vec![
+            (
+                b"/home/u/a/result".to_vec(),
+                b"/nix/store/aaaa-foo".to_vec(),
+            ),
+            (
+                b"/home/u/b/result".to_vec(),
+                b"/nix/store/aaaa-bar".to_vec(),
+            ),
+        ]);
+        let di = crate::reduction::keep_reachable(crate::backend::read(store).unwrap());
+        let mut usages = root_usages(&di);
+        let summary = |usages: &[RootUsage]| -> Vec<(String, u64, u64, u64)> {
+            usages
+                .iter()
+                .map(|u| {
+                    (
+                        String::from_utf8_lossy(&di.graph[u.root].description.label()).into_owned(),
+                        u.exclusive,
+                        u.closure,
+                        u.shared,
+                    )
+                })
+                .collect()
+        };
+        sort_roots(&di, &mut usages, RootColumn::Exclusive);
+        assert_eq!(
+            summary(&usages),
+            vec![
+                ("/home/u/b/result".into(), 100, 1100, 1000),
+                ("/home/u/a/result".into(), 10, 1010, 1000),
+            ]
+        );
+        sort_roots(&di, &mut usages, RootColumn::Name);
+        assert_eq!(summary(&usages)[0].0, "/home/u/a/result");
+    }
+
+    #[test]
+    fn overlap_matrix() {
+        let mut store = MemoryStore::new();
+        let gen1 = add(&mut store, "system-1", 1);
+        let gen2 = add(&mut store, "system-2", 2);
+        let gen3 = add(&mut store, "system-3", 3);
+        let old = add(&mut store, "glibc-2.38", 1000);
+        let new = add(&mut store, "glibc-2.39", 1000);
+        for &(from, to) in &[(gen1, old), (gen2, old), (gen3, new)] {
+            store.add_reference(from, to);
+        }
+        // these links do not exist: generations are sorted by number
+        let numbers = [2, 9, 10];
+        store.add_gc_roots(numbers.iter().enumerate().map(|(i, n)| {
+            (
+                format!("/nonexistent/nix-du/system-{n}-link").into_bytes(),
+                format!("/nix/store/aaaa-system-{}", i + 1).into_bytes(),
+            )
+        }));
+        let di = crate::reduction::condense(crate::reduction::keep_reachable(
+            crate::backend::read(store).unwrap(),
+        ));
+        let overlap = overlap(&di, 10);
+        let names: Vec<_> = overlap
+            .roots
+            .iter()
+            .map(|&idx| di.graph[idx].name().into_owned())
+            .collect();
+        assert_eq!(
+            names,
+            numbers
+                .iter()
+                .map(|n| format!("/nonexistent/nix-du/system-{n}-link").into_bytes())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            overlap.shared,
+            vec![vec![1001, 1000, 0], vec![1000, 1002, 0], vec![0, 0, 1003]]
+        );
+        assert!(overlap.similarity(0, 1) > 0.99);
+        assert_eq!(overlap.similarity(0, 2), 0.);
+        let mut csv = Vec::new();
+        print_overlap_csv(&di, &overlap, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(
+            csv.lines().nth(2),
+            Some("/nonexistent/nix-du/system-9-link,1000,1002,0")
+        );
+        assert_eq!(
+            csv_field("generation 3, current"),
+            "\"generation 3, current\""
+        );
+        // the largest closures are kept
+        assert_eq!(super::overlap(&di, 1).shared, vec![vec![1003]]);
+    }
+}
@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: LGPL-3.0
+
+//! User supplied rules to name and classify indirect gc roots, read with `--rules`.
+//!
+//! The file is a json list of rules like
+//! `{"regex": "^/var/lib/ci/roots/(?P<job>[^/]*)/(?P<n>[0-9]*)$", "label": "CI job $job, build $n", "category": "ci"}`.
+//! The first rule whose regex matches the path of an indirect root gives its label, where
+//! `$name` and `$1` are replaced by the corresponding capture group, and its category.
+//! These rules take precedence over the built-in ones.
+
+use crate::backend::invalid_data;
+use serde::Deserialize;
+use std::io::{self, Read};
+use std::sync::OnceLock;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    regex: String,
+    label: String,
+    category: String,
+}
+
+/// A rule to name and classify the indirect roots whose path matches `regex`
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub regex: regex::Regex,
+    /// a template for `regex::Captures::expand`
+    pub label: String,
+    pub category: String,
+}
+
+impl Rule {
+    /// The label and category of the root at `path`, if this rule matches.
+    pub fn apply(&self, path: &str) -> Option<(String, &str)> {
+        let captures = self.regex.captures(path)?;
+        let mut label = String::new();
+        captures.expand(&self.label, &mut label);
+        Some((label, &self.category))
+    }
+}
+
+static RULES: OnceLock<Vec<Rule>> = OnceLock::new();
+
+/// The rules given with `--rules`, if any
+pub fn rules() -> &'static [Rule] {
+    RULES.get().map(Vec::as_slice).unwrap_or(&[])
+}
+
+/// Sets the rules. Can only be called once.
+pub fn set_rules(rules: Vec<Rule>) {
+    RULES.set(rules).expect("rules were already set");
+}
+
+/// Parses a rule file.
+pub fn parse<R: Read>(r: R) -> io::Result<Vec<Rule>> {
+    let raw: Vec<RawRule> = serde_json::from_reader(r)?;
+    raw.into_iter()
+        .enumerate()
+        .map(|(i, rule)| {
+            let regex = regex::Regex::new(&rule.regex)
+                .map_err(|e| invalid_data(format!("rule {}: {}", i + 1, e)))?;
+            Ok(Rule {
+                regex,
+                label: rule.label,
+                category: rule.category,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ci_rule() {
+        let json = r#"[
+            {"regex": "^/var/lib/ci/roots/(?P<job>[^/]*)/(?P<n>[0-9]*)$", "label": "CI job $job, build $n", "category": "ci"},
+            {"regex": "^/var/lib/ci/", "label": "other CI root", "category": "ci"}
+        ]"#;
+        let rules = parse(json.as_bytes()).unwrap();
+        assert_eq!(
+            rules[0].apply("/var/lib/ci/roots/nightly/12"),
+            Some(("CI job nightly, build 12".to_string(), "ci"))
+        );
+        assert_eq!(rules[0].apply("/var/lib/ci/roots/nightly"), None);
+        assert_eq!(
+            rules[1].apply("/var/lib/ci/roots/nightly"),
+            Some(("other CI root".to_string(), "ci"))
+        );
+        assert!(parse(r#"[{"regex": "(", "label": "", "category": ""}]"#.as_bytes()).is_err());
+        assert!(parse(r#"[{"regex": "a", "label": ""}]"#.as_bytes()).is_err());
+    }
+}
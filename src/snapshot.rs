@@ -0,0 +1,243 @@
+// SPDX-License-Identifier: LGPL-3.0
+
+//! A versioned, machine readable snapshot of a `DepInfos`.
+//!
+//! Written by `--dump` and read back by `--load`, so that the reduction pipeline can be
+//! re-run without querying the store again.
+
+use crate::backend::invalid_data;
+use crate::depgraph::*;
+use petgraph::prelude::NodeIndex;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+
+/// Bump this when the format changes in an incompatible way.
+const VERSION: u32 = 1;
+
+/// Paths are usually utf8, but not necessarily.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+enum Bytes {
+    Utf8(String),
+    Raw(Vec<u8>),
+}
+
+impl From<&[u8]> for Bytes {
+    fn from(path: &[u8]) -> Self {
+        match std::str::from_utf8(path) {
+            Ok(s) => Bytes::Utf8(s.to_owned()),
+            Err(_) => Bytes::Raw(path.to_vec()),
+        }
+    }
+}
+
+impl From<Bytes> for Path {
+    fn from(b: Bytes) -> Self {
+        match b {
+            Bytes::Utf8(s) => s.into_bytes(),
+            Bytes::Raw(v) => v,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Node {
+    kind: NodeKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<Bytes>,
+    size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    info: Option<StorePathInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Snapshot {
+    version: u32,
+    root: u32,
+    metadata: SizeMetadata,
+    nodes: Vec<Node>,
+    edges: Vec<(u32, u32)>,
+}
+
+fn description(kind: NodeKind, path: Option<Bytes>) -> io::Result<NodeDescription> {
+    use self::NodeKind::*;
+    let path = path.map(Vec::<u8>::from);
+    let res = match (kind, path) {
+        (Dummy, None) => NodeDescription::Dummy,
+        (FilteredOut, None) => NodeDescription::FilteredOut,
+        (Transient, None) => NodeDescription::Transient,
+        (Path, Some(p)) => NodeDescription::Path(p),
+        (Link, Some(p)) => NodeDescription::Link(p),
+        (Memory, Some(p)) => NodeDescription::Memory(p),
+        (Temporary, Some(p)) => NodeDescription::Temporary(p),
+        (Shared, Some(p)) => NodeDescription::Shared(p),
+        (Process, Some(p)) => NodeDescription::Process(p),
+        (Build, Some(p)) => NodeDescription::Build(p),
+        (Group, Some(p)) => NodeDescription::Group(p),
+        (Other, Some(p)) => NodeDescription::Other(p),
+        (kind, path) => {
+            return Err(invalid_data(format!(
+                "node of kind {:?} {} a path",
+                kind,
+                if path.is_some() { "with" } else { "without" }
+            )))
+        }
+    };
+    Ok(res)
+}
+
+/// Writes `di` to `w` as json.
+pub fn dump<W: Write>(di: &DepInfos, w: W) -> io::Result<()> {
+    let snapshot = Snapshot {
+        version: VERSION,
+        root: di.root.index() as u32,
+        metadata: di.metadata.clone(),
+        nodes: di
+            .graph
+            .raw_nodes()
+            .iter()
+            .map(|n| Node {
+                kind: n.weight.kind(),
+                path: n
+                    .weight
+                    .description
+                    .path()
+                    .map(|p| Bytes::from(p.as_slice())),
+                size: n.weight.size,
+                info: n.weight.info.as_deref().cloned(),
+            })
+            .collect(),
+        edges: di
+            .graph
+            .raw_edges()
+            .iter()
+            .map(|e| (e.source().index() as u32, e.target().index() as u32))
+            .collect(),
+    };
+    serde_json::to_writer(w, &snapshot)?;
+    Ok(())
+}
+
+/// Reads a `DepInfos` written by `dump`.
+pub fn load<R: Read>(r: R) -> io::Result<DepInfos> {
+    let snapshot: Snapshot = serde_json::from_reader(r)?;
+    if snapshot.version != VERSION {
+        return Err(invalid_data(format!(
+            "unsupported snapshot version {} (expected {})",
+            snapshot.version, VERSION
+        )));
+    }
+    let mut graph = DepGraph::with_capacity(snapshot.nodes.len(), snapshot.edges.len());
+    for node in snapshot.nodes {
+        graph.add_node(DepNode {
+            description: description(node.kind, node.path)?,
+            size: node.size,
+            info: node.info.map(Box::new),
+        });
+    }
+    let n = graph.node_count() as u32;
+    if snapshot.root >= n {
+        return Err(invalid_data(format!(
+            "root {} out of bounds",
+            snapshot.root
+        )));
+    }
+    for (from, to) in snapshot.edges {
+        if from >= n || to >= n {
+            return Err(invalid_data(format!("edge {from} -> {to} out of bounds")));
+        }
+        graph.add_edge(NodeIndex::from(from), NodeIndex::from(to), ());
+    }
+    Ok(DepInfos {
+        graph,
+        root: NodeIndex::from(snapshot.root),
+        metadata: snapshot.metadata,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enum_map::enum_map;
+
+    #[test]
+    fn roundtrip() {
+        use self::NodeDescription::*;
+        let mut g = DepGraph::new();
+        let descriptions = vec![
+            Path(b"/nix/store/aaaa-foo".to_vec()),
+            Link(b"/home/user/\xffresult".to_vec()),
+            Memory(b"{censored}".to_vec()),
+            Temporary(b"{temp:12}".to_vec()),
+            Shared(b"foo".to_vec()),
+            Process(b"firefox (pid 12)".to_vec()),
+            Build(b"nix-daemon (pid 13), building foo.drv".to_vec()),
+            Group(b"alice's roots".to_vec()),
+            Other(b"{kvm:12}".to_vec()),
+            Transient,
+            FilteredOut,
+            Dummy,
+        ];
+        for (i, description) in descriptions.into_iter().enumerate() {
+            let info = match description {
+                Path(_) => Some(Box::new(StorePathInfo {
+                    deriver: Some("/nix/store/bbbb-foo.drv".into()),
+                    registration_time: Some(1_600_000_000),
+                    content_addressed: false,
+                    signed: true,
+                    ultimate: false,
+                })),
+                _ => None,
+            };
+            g.add_node(DepNode {
+                description,
+                size: i as u64 * 10,
+                info,
+            });
+        }
+        for i in 0..11 {
+            g.add_edge(NodeIndex::new(11), NodeIndex::new(i), ());
+        }
+        g.add_edge(NodeIndex::new(1), NodeIndex::new(0), ());
+        let mut di = DepInfos {
+            graph: g,
+            root: NodeIndex::new(11),
+            metadata: SizeMetadata {
+                reachable: Reachability::Connected,
+                dedup: DedupAwareness::Aware,
+                size: enum_map! { _ => enum_map!{ _ => None }},
+            },
+        };
+        di.record_metadata();
+
+        let mut buf = Vec::new();
+        dump(&di, &mut buf).unwrap();
+        let new = load(buf.as_slice()).unwrap();
+        new.check_metadata();
+        assert_eq!(new.root, di.root);
+        assert_eq!(
+            new.metadata.size[DedupAwareness::Aware][Reachability::Connected],
+            Some(660)
+        );
+        for (old, new) in di.graph.raw_nodes().iter().zip(new.graph.raw_nodes()) {
+            assert_eq!(old.weight, new.weight);
+        }
+        let edges = |di: &DepInfos| {
+            di.graph
+                .raw_edges()
+                .iter()
+                .map(|e| (e.source(), e.target()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(edges(&di), edges(&new));
+    }
+
+    #[test]
+    fn bad_version() {
+        let json = r#"{"version":VERSION,"root":0,"metadata":{"reachable":"connected","dedup":"unaware",
+            "size":{"aware":{"connected":null,"disconnected":null},"unaware":{"connected":0,"disconnected":null}}},
+            "nodes":[{"kind":"dummy","size":0}],"edges":[]}"#;
+        assert!(load(json.replace("VERSION", "1").as_bytes()).is_ok());
+        assert!(load(json.replace("VERSION", "1000").as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,264 @@
+// SPDX-License-Identifier: LGPL-3.0
+
+//! Reads the dependency graph directly from the sqlite database of a local nix store,
+//! usually `/nix/var/nix/db/db.sqlite`.
+//!
+//! This is much faster than querying path infos one by one through libnixstore, and works
+//! on a database copied from another machine.
+
+use crate::backend::{MemoryStore, Roots, StoreBackend};
+use crate::depgraph::*;
+use crate::gcroots;
+use rusqlite::{Connection, OpenFlags};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io;
+use std::path::{Path as FsPath, PathBuf};
+
+/// The garbage collector settings which influence liveness.
+#[derive(Debug, Clone, Copy)]
+pub struct GcSettings {
+    pub keep_outputs: bool,
+    pub keep_derivations: bool,
+}
+
+impl Default for GcSettings {
+    fn default() -> Self {
+        GcSettings {
+            keep_outputs: false,
+            keep_derivations: true,
+        }
+    }
+}
+
+impl GcSettings {
+    /// Reads `keep-outputs` and `keep-derivations` from `nix.conf` in `conf_dir`.
+    /// Only simple `key = value` lines are understood; `include` directives are not
+    /// followed.
+    pub fn from_conf_dir(conf_dir: &FsPath) -> Self {
+        let mut res = Self::default();
+        let content = match std::fs::read_to_string(conf_dir.join("nix.conf")) {
+            Ok(c) => c,
+            Err(_) => return res,
+        };
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or("");
+            let mut kv = line.splitn(2, '=');
+            let (key, value) = match (kv.next(), kv.next()) {
+                (Some(k), Some(v)) => (k.trim(), v.trim()),
+                _ => continue,
+            };
+            let value = value == "true";
+            match key {
+                "keep-outputs" | "gc-keep-outputs" => res.keep_outputs = value,
+                "keep-derivations" | "gc-keep-derivations" => res.keep_derivations = value,
+                _ => (),
+            }
+        }
+        res
+    }
+}
+
+fn to_io(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+/// Returns the state directory (usually `/nix/var/nix`) containing a database.
+pub fn state_dir_of(db: &FsPath) -> Option<PathBuf> {
+    db.parent().and_then(FsPath::parent).map(FsPath::to_owned)
+}
+
+/// The database at `path`, as a backend.
+///
+/// If `closure_of` is not empty, only the closure of these roots is kept, like
+/// `LibNixStore` with roots.
+/// Otherwise, roots are taken from `roots` if given, or found in `state_dir`. The latter
+/// also reads temporary roots and scans running processes for roots if `local_roots` is
+/// true.
+pub struct Database<'a> {
+    pub path: &'a FsPath,
+    pub closure_of: Vec<gcroots::Root>,
+    pub roots: Option<Vec<gcroots::Root>>,
+    pub state_dir: Option<&'a FsPath>,
+    pub local_roots: bool,
+    pub settings: GcSettings,
+}
+
+impl StoreBackend for Database<'_> {
+    fn contents(self) -> io::Result<MemoryStore> {
+        let conn = Connection::open_with_flags(
+            self.path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )
+        .map_err(to_io)?;
+
+        let mut store = MemoryStore::new();
+        // nix ids are not necessarily contiguous
+        let mut id_to_idx: HashMap<i64, usize> = HashMap::new();
+        let mut derivers: Vec<(usize, Path)> = Vec::new();
+        {
+            let mut stmt = conn
+                .prepare("SELECT id, path, narSize, deriver, registrationTime, ultimate, sigs, ca FROM ValidPaths")
+                .map_err(to_io)?;
+            let mut rows = stmt.query([]).map_err(to_io)?;
+            while let Some(row) = rows.next().map_err(to_io)? {
+                let id: i64 = row.get(0).map_err(to_io)?;
+                let path: String = row.get(1).map_err(to_io)?;
+                let size: Option<i64> = row.get(2).map_err(to_io)?;
+                let deriver: Option<String> = row.get(3).map_err(to_io)?;
+                let registration_time: i64 = row.get(4).map_err(to_io)?;
+                let ultimate: Option<i64> = row.get(5).map_err(to_io)?;
+                let sigs: Option<String> = row.get(6).map_err(to_io)?;
+                let ca: Option<String> = row.get(7).map_err(to_io)?;
+                let idx = store.add_path(path.into_bytes(), size.unwrap_or(0) as u64);
+                store.set_info(
+                    idx,
+                    StorePathInfo {
+                        deriver: deriver.clone(),
+                        registration_time: u64::try_from(registration_time).ok(),
+                        content_addressed: ca.is_some_and(|ca| !ca.is_empty()),
+                        signed: sigs.is_some_and(|sigs| !sigs.trim().is_empty()),
+                        ultimate: ultimate.unwrap_or(0) != 0,
+                    },
+                );
+                id_to_idx.insert(id, idx);
+                if let Some(deriver) = deriver {
+                    derivers.push((idx, deriver.into_bytes()));
+                }
+            }
+        }
+        {
+            let mut stmt = conn
+                .prepare("SELECT referrer, reference FROM Refs")
+                .map_err(to_io)?;
+            let mut rows = stmt.query([]).map_err(to_io)?;
+            while let Some(row) = rows.next().map_err(to_io)? {
+                let from: i64 = row.get(0).map_err(to_io)?;
+                let to: i64 = row.get(1).map_err(to_io)?;
+                if let (Some(&from), Some(&to)) = (id_to_idx.get(&from), id_to_idx.get(&to)) {
+                    store.add_reference(from, to);
+                }
+            }
+        }
+        for (idx, deriver) in derivers {
+            if let Some(drv) = store.index_of(&deriver) {
+                if self.settings.keep_derivations {
+                    store.add_reference(idx, drv);
+                }
+                if self.settings.keep_outputs {
+                    store.add_reference(drv, idx);
+                }
+            }
+        }
+
+        let store_dir = if id_to_idx.is_empty() {
+            None
+        } else {
+            gcroots::store_dir_of(store.path(0)).map(<[u8]>::to_vec)
+        };
+
+        if !self.closure_of.is_empty() {
+            let mut closure = Vec::with_capacity(self.closure_of.len());
+            for (name, path) in self.closure_of {
+                let idx = store_dir
+                    .as_ref()
+                    .and_then(|store_dir| gcroots::to_store_path(&path, store_dir))
+                    .and_then(|path| store.index_of(&path));
+                match idx {
+                    Some(idx) => closure.push((name, idx)),
+                    None => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!("'{}' is not a valid path", String::from_utf8_lossy(&path)),
+                        ))
+                    }
+                }
+            }
+            store.roots = Roots::Closure(closure);
+            return Ok(store);
+        }
+
+        let roots = match (self.roots, store_dir, self.state_dir) {
+            (Some(r), _, _) => r,
+            (None, Some(store_dir), Some(state_dir)) => {
+                gcroots::find_roots(state_dir, &store_dir, self.local_roots)?
+            }
+            (None, _, _) => vec![],
+        };
+        store.add_gc_roots(roots);
+        Ok(store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn read_db() {
+        let tmp = TempDir::new("sqlite");
+        let state = tmp.join("var/nix");
+        std::fs::create_dir_all(state.join("db")).unwrap();
+        std::fs::create_dir_all(state.join("gcroots")).unwrap();
+        std::os::unix::fs::symlink("/nix/store/aaaa-foo", state.join("gcroots/foo")).unwrap();
+        let db = state.join("db/db.sqlite");
+        {
+            let conn = Connection::open(&db).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE ValidPaths (id integer primary key autoincrement not null, path text unique not null, hash text not null, registrationTime integer not null, deriver text, narSize integer, ultimate integer, sigs text, ca text);
+                 CREATE TABLE Refs (referrer integer not null, reference integer not null, primary key (referrer, reference));
+                 INSERT INTO ValidPaths VALUES (1, '/nix/store/aaaa-foo', 'h', 1600000000, '/nix/store/cccc-foo.drv', 100, 1, NULL, NULL);
+                 INSERT INTO ValidPaths VALUES (2, '/nix/store/bbbb-bar', 'h', 0, NULL, 50, 1, NULL, NULL);
+                 INSERT INTO ValidPaths VALUES (5, '/nix/store/cccc-foo.drv', 'h', 0, NULL, 1, 1, NULL, NULL);
+                 INSERT INTO ValidPaths VALUES (6, '/nix/store/dddd-dead', 'h', 0, NULL, 1000, 1, NULL, NULL);
+                 INSERT INTO Refs VALUES (1, 2);
+                 INSERT INTO Refs VALUES (1, 1);",
+            )
+            .unwrap();
+        }
+        let di = backend::read(Database {
+            path: &db,
+            closure_of: vec![],
+            roots: None,
+            state_dir: Some(&state),
+            local_roots: false,
+            settings: GcSettings::default(),
+        })
+        .unwrap();
+        let rooted = backend::read(Database {
+            path: &db,
+            closure_of: vec![(
+                b"/run/foo".to_vec(),
+                b"/nix/store/aaaa-foo/bin/foo".to_vec(),
+            )],
+            roots: None,
+            state_dir: None,
+            local_roots: false,
+            settings: GcSettings {
+                keep_derivations: false,
+                keep_outputs: false,
+            },
+        })
+        .unwrap();
+
+        di.check_metadata();
+        assert_eq!(di.graph.node_count(), 6);
+        // foo -> bar, foo -> foo.drv, root -> foo, dummy -> root
+        assert_eq!(di.graph.edge_count(), 4);
+        assert_eq!(di.reachable_size(), 151);
+        assert_eq!(di.size(), 1151);
+
+        let foo = di.roots().next().unwrap();
+        let foo = di.graph.neighbors(foo).next().unwrap();
+        let info = di.graph[foo].info.as_ref().unwrap();
+        assert_eq!(info.registration_time, Some(1_600_000_000));
+        assert!(info.ultimate);
+        assert!(!info.is_substituted());
+
+        rooted.check_metadata();
+        assert_eq!(rooted.graph.node_count(), 2);
+        assert_eq!(rooted.reachable_size(), 150);
+    }
+}
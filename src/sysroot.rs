@@ -0,0 +1,167 @@
+// SPDX-License-Identifier: LGPL-3.0
+
+//! Support for `--system-root`: analysing a system whose root filesystem is mounted
+//! somewhere else than `/`, for example `/mnt` when rescuing a machine.
+//!
+//! Paths in the graph are always paths as seen from the analysed system, like
+//! `/nix/store/hash-name` or `/home/user/result`. They must go through `host_path`
+//! before being accessed on this machine.
+
+use std::ffi::OsStr;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+static SYSTEM_ROOT: OnceLock<PathBuf> = OnceLock::new();
+static UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+/// The directory where the root filesystem of the analysed system is mounted, if it is
+/// not `/`.
+pub fn system_root() -> Option<&'static Path> {
+    SYSTEM_ROOT.get().map(PathBuf::as_path)
+}
+
+/// Sets the system root. Can only be called once.
+pub fn set_system_root(root: PathBuf) {
+    SYSTEM_ROOT.set(root).expect("system root was already set");
+}
+
+/// Whether the files of the analysed system can be read from this machine, at `/` or
+/// under the system root. They cannot when the graph comes from a dump, a binary cache
+/// or the database of another machine: the paths of its gc roots must not be looked up
+/// on this machine, which has files of its own at the same place.
+pub fn is_available() -> bool {
+    !UNAVAILABLE.load(Ordering::Relaxed)
+}
+
+/// Records that the files of the analysed system cannot be read from this machine.
+pub fn set_unavailable() {
+    UNAVAILABLE.store(true, Ordering::Relaxed);
+}
+
+/// Returns where the absolute path `path` of a system mounted at `root` lives on this
+/// machine.
+pub fn rebase(root: Option<&Path>, path: &Path) -> PathBuf {
+    match root {
+        None => path.to_owned(),
+        Some(root) => {
+            let mut res = root.to_owned();
+            res.extend(path.components().filter(|c| *c != Component::RootDir));
+            res
+        }
+    }
+}
+
+/// Inverse of `rebase`: returns the path in the analysed system of `host`, a path on this
+/// machine, or `None` if it is outside of `root`.
+fn unrebase(root: Option<&Path>, host: &Path) -> Option<PathBuf> {
+    match root {
+        None => Some(host.to_owned()),
+        Some(root) => host
+            .strip_prefix(root)
+            .ok()
+            .map(|relative| Path::new("/").join(relative)),
+    }
+}
+
+/// Returns where the absolute path `path` of the analysed system lives on this machine.
+pub fn host_path(path: &[u8]) -> PathBuf {
+    rebase(system_root(), Path::new(OsStr::from_bytes(path)))
+}
+
+/// Inverse of `host_path`.
+pub fn system_path(host: &Path) -> Option<PathBuf> {
+    unrebase(system_root(), host)
+}
+
+/// Returns the canonical form of the absolute path `path` of a system mounted at `root`,
+/// resolving absolute symlinks relative to `root` instead of `/`.
+pub fn canonicalize_in(root: Option<&Path>, path: &Path) -> io::Result<PathBuf> {
+    let root = match root {
+        None => return path.canonicalize(),
+        Some(root) => root,
+    };
+    // maximum number of symlinks to follow, like linux
+    let mut budget = 40;
+    let mut res = PathBuf::from("/");
+    let mut todo: Vec<_> = path
+        .components()
+        .rev()
+        .map(|c| c.as_os_str().to_owned())
+        .collect();
+    while let Some(component) = todo.pop() {
+        match Path::new(&component).components().next() {
+            Some(Component::RootDir) => res = PathBuf::from("/"),
+            Some(Component::CurDir) | None => (),
+            Some(Component::ParentDir) => {
+                res.pop();
+            }
+            Some(Component::Normal(name)) => {
+                res.push(name);
+                let host = rebase(Some(root), &res);
+                if host.symlink_metadata()?.file_type().is_symlink() {
+                    if budget == 0 {
+                        return Err(io::Error::other(format!(
+                            "too many levels of symbolic links in {}",
+                            path.display()
+                        )));
+                    }
+                    budget -= 1;
+                    let target = std::fs::read_link(&host)?;
+                    res.pop();
+                    todo.extend(target.components().rev().map(|c| c.as_os_str().to_owned()));
+                }
+            }
+            Some(Component::Prefix(_)) => unreachable!("no prefix on unix"),
+        }
+    }
+    Ok(res)
+}
+
+/// Returns the canonical form of the absolute path `path` of the analysed system, as a
+/// path of the analysed system.
+pub fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+    canonicalize_in(system_root(), path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn rebasing() {
+        let root = Some(Path::new("/mnt"));
+        assert_eq!(
+            rebase(root, Path::new("/nix/store/aaaa-foo")),
+            PathBuf::from("/mnt/nix/store/aaaa-foo")
+        );
+        assert_eq!(
+            rebase(None, Path::new("/nix/store/aaaa-foo")),
+            PathBuf::from("/nix/store/aaaa-foo")
+        );
+        assert_eq!(
+            unrebase(root, Path::new("/mnt/home/user")),
+            Some(PathBuf::from("/home/user"))
+        );
+        assert_eq!(unrebase(root, Path::new("/home/user")), None);
+    }
+
+    #[test]
+    fn canonicalize_under_root() {
+        let tmp = TempDir::new("sysroot");
+        std::fs::create_dir_all(tmp.join("nix/store/aaaa-foo/bin")).unwrap();
+        std::fs::create_dir_all(tmp.join("run")).unwrap();
+        symlink("/nix/store/aaaa-foo", tmp.join("run/current-system")).unwrap();
+        symlink("current-system/bin", tmp.join("run/bin")).unwrap();
+        let current = canonicalize_in(Some(&*tmp), Path::new("/run/current-system"));
+        let bin = canonicalize_in(Some(&*tmp), Path::new("/run/bin/"));
+        let missing = canonicalize_in(Some(&*tmp), Path::new("/run/missing"));
+        assert_eq!(current.unwrap(), PathBuf::from("/nix/store/aaaa-foo"));
+        assert_eq!(bin.unwrap(), PathBuf::from("/nix/store/aaaa-foo/bin"));
+        assert!(missing.is_err());
+    }
+}
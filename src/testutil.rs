@@ -0,0 +1,42 @@
+// SPDX-License-Identifier: LGPL-3.0
+
+//! Helpers shared by unit tests.
+
+use crate::backend::MemoryStore;
+use std::path::{Path, PathBuf};
+
+/// A directory `nix-du-NAME-PID` in the temporary directory, removed when dropped, even
+/// if the test fails.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("nix-du-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl std::ops::Deref for TempDir {
+    type Target = Path;
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// The store path `/nix/store/aaaa-NAME`
+pub fn store_path(name: &str) -> Vec<u8> {
+    format!("/nix/store/aaaa-{name}").into_bytes()
+}
+
+/// Adds the store path `/nix/store/aaaa-NAME` of `size` bytes to `store`, and returns
+/// its index.
+pub fn add(store: &mut MemoryStore, name: &str, size: u64) -> usize {
+    store.add_path(store_path(name), size)
+}
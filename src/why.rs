@@ -0,0 +1,230 @@
+// SPDX-License-Identifier: LGPL-3.0
+
+//! `nix-du why PATH`: the gc roots keeping a store path alive, with a shortest chain of
+//! references from each of them to the path.
+
+use crate::depgraph::*;
+use petgraph::prelude::NodeIndex;
+use petgraph::Direction;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+/// Why an edge of the dependency graph keeps its target alive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// from a gc root to the store path it points to
+    Root,
+    /// the source references the target
+    Reference,
+    /// from a derivation to its output, with `keep-outputs = true`
+    KeepOutputs,
+    /// from an output to its derivation, with `keep-derivations = true`
+    KeepDerivations,
+}
+
+impl std::fmt::Display for EdgeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            EdgeKind::Root => "gc root",
+            EdgeKind::Reference => "reference",
+            EdgeKind::KeepOutputs => "keep-outputs",
+            EdgeKind::KeepDerivations => "keep-derivations",
+        })
+    }
+}
+
+fn is_drv(node: &DepNode) -> bool {
+    node.description
+        .path()
+        .is_some_and(|path| path.ends_with(b".drv"))
+}
+
+/// Guesses why the edge `from -> to` exists. Outputs do not reference derivations, and
+/// derivations do not reference their outputs, so such edges come from the gc settings.
+/// When the deriver of `to` is unknown, an output is recognised by its name.
+pub fn edge_kind(di: &DepInfos, from: NodeIndex, to: NodeIndex) -> EdgeKind {
+    let (source, target) = (&di.graph[from], &di.graph[to]);
+    if source.kind() != NodeKind::Path {
+        return EdgeKind::Root;
+    }
+    match (is_drv(source), is_drv(target)) {
+        (false, true) => EdgeKind::KeepDerivations,
+        (true, false) => {
+            let source_path = source.description.path().unwrap();
+            let is_output = match target.info.as_ref().and_then(|i| i.deriver.as_ref()) {
+                Some(deriver) => deriver.as_bytes() == source_path.as_slice(),
+                None => {
+                    let name = source.name();
+                    let stem = &name[..name.len() - b".drv".len()];
+                    target.name().starts_with(stem)
+                }
+            };
+            if is_output {
+                EdgeKind::KeepOutputs
+            } else {
+                EdgeKind::Reference
+            }
+        }
+        _ => EdgeKind::Reference,
+    }
+}
+
+/// For each gc root keeping `target` alive, a shortest chain of nodes from the root to
+/// `target`. The shortest chains come first.
+pub fn chains(di: &DepInfos, target: NodeIndex) -> Vec<Vec<NodeIndex>> {
+    // breadth first search backwards from target: next[idx] is the next node on a
+    // shortest path from idx to target
+    let mut next = vec![None; di.graph.node_count()];
+    let mut order = Vec::new();
+    let mut queue = VecDeque::new();
+    next[target.index()] = Some(target);
+    queue.push_back(target);
+    while let Some(idx) = queue.pop_front() {
+        order.push(idx);
+        for referrer in di.graph.neighbors_directed(idx, Direction::Incoming) {
+            if next[referrer.index()].is_none() {
+                next[referrer.index()] = Some(idx);
+                queue.push_back(referrer);
+            }
+        }
+    }
+    let is_root = |idx: NodeIndex| match di.graph[di.root].kind() {
+        NodeKind::Dummy => di.graph.contains_edge(di.root, idx),
+        _ => idx == di.root,
+    };
+    let mut res: Vec<Vec<NodeIndex>> = order
+        .into_iter()
+        .filter(|&idx| is_root(idx))
+        .map(|root| {
+            let mut chain = vec![root];
+            let mut idx = root;
+            while idx != target {
+                idx = next[idx.index()].unwrap();
+                chain.push(idx);
+            }
+            chain
+        })
+        .collect();
+    res.sort_by_cached_key(|chain| {
+        (
+            chain.len(),
+            di.graph[chain[0]].description.label().into_owned(),
+        )
+    });
+    res
+}
+
+/// Prints each chain: the name of the root, then one line per edge with its kind and the
+/// store path it leads to.
+pub fn print_chains<W: Write>(
+    di: &DepInfos,
+    target: NodeIndex,
+    chains: &[Vec<NodeIndex>],
+    w: &mut W,
+) -> io::Result<()> {
+    let target = String::from_utf8_lossy(di.graph[target].description.path().unwrap());
+    if chains.is_empty() {
+        return writeln!(w, "{target} is not kept alive by any gc root");
+    }
+    writeln!(w, "{} gc roots keep {} alive:", chains.len(), target)?;
+    for chain in chains {
+        writeln!(w)?;
+        let root = &di.graph[chain[0]];
+        write!(w, "{}", String::from_utf8_lossy(&root.name()))?;
+        match root.description.path() {
+            Some(path) if root.description.label().as_ref() != path.as_slice() => {
+                writeln!(w, " ({})", String::from_utf8_lossy(path))?
+            }
+            _ => writeln!(w)?,
+        }
+        for pair in chain.windows(2) {
+            writeln!(
+                w,
+                "  {:>16}  {}",
+                edge_kind(di, pair[0], pair[1]),
+                String::from_utf8_lossy(&di.graph[pair[1]].name())
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MemoryStore;
+    use crate::testutil::add;
+
+    #[test]
+    fn why() {
+        let mut store = MemoryStore::new();
+        let env = add(&mut store, "env", 1);
+        let app = add(&mut store, "app-1.0", 1);
+        let app_drv = add(&mut store, "app-1.0.drv", 1);
+        let cuda = add(&mut store, "cuda-12.2", 1);
+        let cuda_drv = add(&mut store, "cuda-12.2.drv", 1);
+        let doc = add(&mut store, "cuda-12.2-doc", 1);
+        for &(from, to) in &[
+            (env, app),
+            (app, cuda),
+            (app, app_drv),
+            (app_drv, cuda_drv),
+            (cuda_drv, cuda),
+            (doc, cuda_drv),
+        ] {
+            store.add_reference(from, to);
+        }
+        store.add_gc_roots(vec![
+            (b"/home/u/result".to_vec(), b"/nix/store/aaaa-env".to_vec()),
+            (
+                b"/home/u/docs".to_vec(),
+                b"/nix/store/aaaa-cuda-12.2-doc".to_vec(),
+            ),
+            (
+                b"/home/u/unrelated".to_vec(),
+                b"/nix/store/aaaa-app-1.0.drv".to_vec(),
+            ),
+        ]);
+        let di = crate::backend::read(store).unwrap();
+        let cuda = NodeIndex::new(cuda);
+        let chains = chains(&di, cuda);
+        let described: Vec<Vec<String>> = chains
+            .iter()
+            .map(|chain| {
+                let mut res =
+                    vec![String::from_utf8_lossy(&di.graph[chain[0]].description.label()).into()];
+                res.extend(chain.windows(2).map(|pair| {
+                    format!(
+                        "{} {}",
+                        edge_kind(&di, pair[0], pair[1]),
+                        String::from_utf8_lossy(&di.graph[pair[1]].name())
+                    )
+                }));
+                res
+            })
+            .collect();
+        assert_eq!(
+            described,
+            vec![
+                vec![
+                    "/home/u/docs",
+                    "gc root cuda-12.2-doc",
+                    "keep-derivations cuda-12.2.drv",
+                    "keep-outputs cuda-12.2"
+                ],
+                vec![
+                    "/home/u/result",
+                    "gc root env",
+                    "reference app-1.0",
+                    "reference cuda-12.2"
+                ],
+                vec![
+                    "/home/u/unrelated",
+                    "gc root app-1.0.drv",
+                    "reference cuda-12.2.drv",
+                    "keep-outputs cuda-12.2"
+                ],
+            ]
+        );
+    }
+}
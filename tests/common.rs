@@ -583,3 +583,80 @@ dec_test!(
         assert_matches(&real, &expected);
     }
 );
+
+dec_test!(
+    rooted_several = |t| {
+        dec_spec!(spec = (
+              coucou, foo, bar, baz, mux;
+              coucou -> foo, bar -> foo, foo -> baz, coucou -> mux, mux -> baz));
+        // don't keep derivations to keep things simple
+        prepare_store(&spec, "keep-derivations = false\n", &t);
+
+        // what each root holds exclusively, and what they share
+        dec_out!(expected = (
+                coucou 2, bar 1, foo 2;
+                coucou -> foo, bar -> foo));
+        let coucou = t.path("roots/coucou");
+        let bar = t.path("roots/bar");
+        let real = run_and_parse(
+            &["-r", &coucou.to_string_lossy(), "-r", &bar.to_string_lossy()],
+            &t,
+        );
+        assert_matches(&real, &expected);
+    }
+);
+
+dec_test!(
+    path_info = |t| {
+        // no store is needed: the graph is read from the output of nix path-info
+        t.create_file(
+            "path-info.json",
+            r#"{
+            "/nix/store/aaaa-coucou": {"narSize": 100000, "references": ["/nix/store/cccc-foo"]},
+            "/nix/store/bbbb-bar": {"narSize": 100000, "references": ["/nix/store/cccc-foo"]},
+            "/nix/store/cccc-foo": {"narSize": 100000, "references": ["/nix/store/dddd-baz"]},
+            "/nix/store/dddd-baz": {"narSize": 100000, "references": []}
+            }"#,
+        );
+        t.create_file(
+            "roots.txt",
+            "/roots/coucou -> /nix/store/aaaa-coucou\n/roots/bar -> /nix/store/bbbb-bar\n",
+        );
+
+        dec_out!(expected = (
+                coucou 1, bar 1, foo 2;
+                coucou -> foo, bar -> foo));
+        let real = run_and_parse(
+            &["--path-info", "path-info.json", "--gc-roots", "roots.txt"],
+            &t,
+        );
+        assert_matches(&real, &expected);
+
+        // without roots, paths without referrers are the roots
+        let real = run_and_parse(&["--path-info", "path-info.json"], &t);
+        assert_matches(&real, &expected);
+    }
+);
+
+dec_test!(
+    dump_and_load = |t| {
+        dec_spec!(spec = (
+              coucou, foo, bar, baz, mux;
+              coucou -> foo, bar -> foo, foo -> baz, coucou -> mux, mux -> baz));
+        prepare_store(&spec, "", &t);
+
+        let dump = t.path("dump.json");
+        call_self(&t)
+            .arg("--dump")
+            .arg(&dump)
+            .arg("-O0")
+            .expect_success();
+        // --load does not query the store
+        let loaded = run_and_parse(&["--load", &dump.to_string_lossy()], &t);
+
+        dec_out!(expected = (
+                coucou 2, bar 1, foo 2;
+                coucou -> foo, bar -> foo));
+        assert_matches(&loaded, &expected);
+    }
+);
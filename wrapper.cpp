@@ -135,17 +135,31 @@ typedef struct
 } Info;
 extern void register_node(void * graph, path_t * node);
 extern void register_edge(void * graph, unsigned from, unsigned to);
-int populateGraph(void * graph, const char * rootPath)
+int populateGraph(void * graph, const char * const * rootPaths, const char * storeUri)
 {
   using namespace nix;
-  int retcode = handleExceptions("nix-du", [graph, rootPath]() {
+  int retcode = handleExceptions("nix-du", [graph, rootPaths, storeUri]() {
     initNix();
 #ifdef NEEDS_ASYNC
     AsyncIoRoot aio;
 #endif
-    auto store = unwrap_promise(openStore());
+    // without uri, use the store configured by NIX_REMOTE and nix.conf
+    auto store = storeUri ? unwrap_promise(openStore(std::string(storeUri))) : unwrap_promise(openStore());
 
     std::unordered_map<PATH, Info> node_to_id;
+    // full path of a store path
+    auto print_path = [&](const PATH & p) {
+#if NIXVER >= 293 && defined(NIX_IS_ACTUALLY_LIX)
+      std::string path = store->config().storeDir + "/";
+      path.append(p.to_string());
+#elif NIXVER >= 204
+      std::string path = store->storeDir + "/";
+      path.append(p.to_string());
+#else
+        std::string path = p;
+#endif
+      return path;
+    };
     // Registers the node if it was not already registered, and return its path info
     // Returns: pair of a boolean indicating if it was already visited, and path info
     auto get_infos = [&](const PATH & p) {
@@ -158,16 +172,23 @@ int populateGraph(void * graph, const char * rootPath)
         path_t entry;
         entry.is_root = 0;
         entry.size = info.data->narSize;
-#if NIXVER >= 293 && defined(NIX_IS_ACTUALLY_LIX)
-        std::string path = store->config().storeDir + "/";
-        path.append(p.to_string());
-#elif NIXVER >= 204
-        std::string path = store->storeDir + "/";
-        path.append(p.to_string());
+        std::string path = print_path(p);
+        entry.path = path.c_str();
+        std::string deriver;
+        if (DERIVER_IS_EMPTY(info.data->deriver)) {
+          entry.deriver = nullptr;
+        } else {
+          deriver = print_path(DERIVER_GET(info.data->deriver));
+          entry.deriver = deriver.c_str();
+        }
+        entry.registration_time = info.data->registrationTime;
+#if NIXVER >= 204
+        entry.content_addressed = info.data->ca.has_value();
 #else
-          std::string path = info.data->path;
+          entry.content_addressed = !info.data->ca.empty();
 #endif
-        entry.path = path.c_str();
+        entry.has_signatures = !info.data->sigs.empty();
+        entry.ultimate = info.data->ultimate;
         node_to_id[p] = info;
         register_node(graph, &entry);
         return std::make_pair(false, info);
@@ -178,23 +199,28 @@ int populateGraph(void * graph, const char * rootPath)
 
     // queue for graph traversal
     std::vector<PATH> queue;
-    // initialise with either all nodes or just the root we want
-    if (!rootPath) {
+    // store paths corresponding to rootPaths
+    std::vector<PATH> rootDrvs;
+    // initialise with either all nodes or just the roots we want
+    if (!rootPaths) {
       // dump all the store
       std::set<PATH> paths = unwrap_promise(store->queryAllValidPaths());
       std::copy(paths.begin(), paths.end(), std::back_inserter(queue));
     } else {
-      // dump only the recursive closure of rootPath
+      // dump only the recursive closure of rootPaths
+      for (const char * const * rootPath = rootPaths; *rootPath; ++rootPath) {
 #if NIXVER >= 204
-      const PATH rootDrv = store->followLinksToStorePath(rootPath);
+        const PATH rootDrv = store->followLinksToStorePath(*rootPath);
 #else
-        const Path naiveRootPath(rootPath);
-        const PATH rootDrv = store->followLinksToStorePath(naiveRootPath);
+          const Path naiveRootPath(*rootPath);
+          const PATH rootDrv = store->followLinksToStorePath(naiveRootPath);
 #endif
-      if (!unwrap_promise(store->isValidPath(rootDrv))) {
-        throw Error("'%s' is not a valid path", rootPath);
+        if (!unwrap_promise(store->isValidPath(rootDrv))) {
+          throw Error("'%s' is not a valid path", *rootPath);
+        }
+        queue.push_back(rootDrv);
+        rootDrvs.push_back(rootDrv);
       }
-      queue.push_back(rootDrv);
     }
 
     // follow references in graph traversal, register corresponding edges
@@ -237,7 +263,30 @@ int populateGraph(void * graph, const char * rootPath)
       }
     }
 
-    if (!rootPath) {
+#if NIXVER >= 207
+    // binary caches have no gc roots
+    bool hasRoots = dynamic_cast<GcStore *>(&*store) != nullptr;
+    if (!rootPaths && !hasRoots) {
+      std::cerr << "warning: this store does not support listing gc roots, use --root" << std::endl;
+    }
+#else
+    bool hasRoots = true;
+#endif
+
+    if (rootPaths) {
+      // register the requested roots, in order, with an edge to their store path
+      unsigned index = node_to_id.size();
+      for (size_t i = 0; i < rootDrvs.size(); ++i) {
+        path_t entry = {};
+        entry.is_root = 1;
+        entry.size = 0;
+        entry.path = rootPaths[i];
+        register_node(graph, &entry);
+        Info to = get_infos(rootDrvs[i]).second;
+        register_edge(graph, index, to.index);
+        ++index;
+      }
+    } else if (hasRoots) {
       // register roots and add edge to corresponding store path
       unsigned index = node_to_id.size();
 #if NIXVER >= 203
@@ -249,7 +298,7 @@ int populateGraph(void * graph, const char * rootPath)
           std::tie(link, storepath) = root;
 #endif
           if (unwrap_promise(store->isValidPath(storepath))) {
-            path_t entry;
+            path_t entry = {};
             entry.is_root = 1;
             entry.size = link.size();
             entry.path = link.c_str();
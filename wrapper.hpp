@@ -8,6 +8,15 @@ typedef struct
   const char * path;
   uint64_t size;
   int is_root;
+  // the following fields are only meaningful for store paths
+  // null if unknown
+  const char * deriver;
+  // seconds since the epoch, 0 if unknown
+  int64_t registration_time;
+  int content_addressed;
+  int has_signatures;
+  int ultimate;
 } path_t;
-int populateGraph(void * graph, const char * rootPath);
+// rootPaths is either null (read the whole store) or a null terminated array of paths
+int populateGraph(void * graph, const char * const * rootPaths, const char * storeUri);
 }